@@ -0,0 +1,269 @@
+//self play harness: plays a new build against a base build and runs a sequential probability
+//ratio test on the results, stopping once one of the two elo hypotheses is accepted
+//usage: sprt <new engine> <base engine> [nodes <n>] [movetime <ms>] [games <n>]
+//            [elo0 <elo>] [elo1 <elo>] [alpha <p>] [beta <p>]
+use chess::{Board, BoardStatus, ChessMove, Color, MoveGen, Piece};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::str::FromStr;
+//games longer than this are adjudicated as draws
+const MAX_PLIES: usize = 400;
+//every opening is played twice, once with each color
+const OPENINGS: [&str; 16] = [
+    "e2e4 e7e5 g1f3 b8c6",
+    "e2e4 c7c5 g1f3 d7d6",
+    "e2e4 c7c5 b1c3 b8c6",
+    "e2e4 e7e6 d2d4 d7d5",
+    "e2e4 c7c6 d2d4 d7d5",
+    "e2e4 d7d6 d2d4 g8f6",
+    "d2d4 d7d5 c2c4 e7e6",
+    "d2d4 d7d5 c2c4 c7c6",
+    "d2d4 g8f6 c2c4 e7e6",
+    "d2d4 g8f6 c2c4 g7g6",
+    "d2d4 f7f5 g2g3 g8f6",
+    "c2c4 e7e5 b1c3 g8f6",
+    "c2c4 c7c5 g1f3 b8c6",
+    "g1f3 d7d5 g2g3 g8f6",
+    "g1f3 g8f6 c2c4 b7b6",
+    "b2b3 e7e5 c1b2 b8c6",
+];
+enum GameResult {
+    Win,
+    Draw,
+    Loss,
+}
+struct Engine {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+impl Engine {
+    fn new(path: &str) -> Engine {
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap_or_else(|e| panic!("can't start {}: {}", path, e));
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut engine = Engine {
+            child,
+            stdin,
+            stdout,
+        };
+        engine.send("uci");
+        engine.wait_for("uciok");
+        engine.send("setoption name OwnBook value false");
+        return engine;
+    }
+    fn send(&mut self, cmd: &str) {
+        writeln!(self.stdin, "{}", cmd).unwrap();
+        self.stdin.flush().unwrap();
+    }
+    //reads lines until one starts with the given token and returns it
+    fn wait_for(&mut self, token: &str) -> String {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.stdout.read_line(&mut line).unwrap() == 0 {
+                panic!("engine closed its output while waiting for {}", token);
+            }
+            if line.starts_with(token) {
+                return line.trim().to_string();
+            }
+        }
+    }
+    fn new_game(&mut self) {
+        self.send("ucinewgame");
+        self.send("isready");
+        self.wait_for("readyok");
+    }
+    fn best_move(&mut self, moves: &[String], go: &str) -> String {
+        self.send(&format!("position startpos moves {}", moves.join(" ")));
+        self.send(go);
+        let line = self.wait_for("bestmove");
+        return line.split_whitespace().nth(1).unwrap_or("").to_string();
+    }
+}
+impl Drop for Engine {
+    fn drop(&mut self) {
+        self.send("quit");
+        let _ = self.child.wait();
+    }
+}
+fn insufficient_material(board: &Board) -> bool {
+    let heavy = board.pieces(Piece::Pawn) | board.pieces(Piece::Rook) | board.pieces(Piece::Queen);
+    let minors = board.pieces(Piece::Knight) | board.pieces(Piece::Bishop);
+    return heavy.0 == 0 && minors.popcnt() <= 1;
+}
+//plays one game from the opening, the result is from white's perspective
+fn play_game(white: &mut Engine, black: &mut Engine, opening: &str, go: &str) -> GameResult {
+    let mut board = Board::default();
+    let mut moves: Vec<String> = Vec::new();
+    let mut seen: HashMap<u64, u8> = HashMap::new();
+    let mut halfmove_clock = 0;
+    //the opening's positions count towards repetitions like any other
+    seen.insert(board.get_hash(), 1);
+    for m in opening.split_whitespace() {
+        board = board.make_move_new(ChessMove::from_str(m).unwrap());
+        moves.push(m.to_string());
+        *seen.entry(board.get_hash()).or_insert(0) += 1;
+    }
+    white.new_game();
+    black.new_game();
+    while moves.len() < MAX_PLIES {
+        let side = board.side_to_move();
+        let engine = if side == Color::White {
+            &mut *white
+        } else {
+            &mut *black
+        };
+        let mv = match ChessMove::from_str(&engine.best_move(&moves, go)) {
+            Ok(mv) if board.legal(mv) => mv,
+            //an illegal move loses the game
+            _ => {
+                return if side == Color::White {
+                    GameResult::Loss
+                } else {
+                    GameResult::Win
+                };
+            }
+        };
+        let irreversible = board.piece_on(mv.get_source()) == Some(Piece::Pawn)
+            || board.piece_on(mv.get_dest()).is_some();
+        halfmove_clock = if irreversible { 0 } else { halfmove_clock + 1 };
+        board = board.make_move_new(mv);
+        moves.push(mv.to_string());
+        match board.status() {
+            BoardStatus::Checkmate => {
+                return if side == Color::White {
+                    GameResult::Win
+                } else {
+                    GameResult::Loss
+                };
+            }
+            BoardStatus::Stalemate => return GameResult::Draw,
+            BoardStatus::Ongoing => {}
+        }
+        let count = seen.entry(board.get_hash()).or_insert(0);
+        *count += 1;
+        if *count >= 3 || halfmove_clock >= 100 || insufficient_material(&board) {
+            return GameResult::Draw;
+        }
+        if MoveGen::new_legal(&board).len() == 0 {
+            return GameResult::Draw;
+        }
+    }
+    return GameResult::Draw;
+}
+fn expected_score(elo: f64) -> f64 {
+    return 1.0 / (1.0 + 10f64.powf(-elo / 400.0));
+}
+//generalized sprt log likelihood ratio for the trinomial (win, draw, loss) model
+fn llr(wins: u32, draws: u32, losses: u32, elo0: f64, elo1: f64) -> f64 {
+    let n = (wins + draws + losses) as f64;
+    if n == 0.0 {
+        return 0.0;
+    }
+    let score = (wins as f64 + draws as f64 / 2.0) / n;
+    let variance = (wins as f64 * (1.0 - score).powi(2)
+        + draws as f64 * (0.5 - score).powi(2)
+        + losses as f64 * score.powi(2))
+        / n;
+    //only when every game had the same result, there is nothing to tell the hypotheses apart
+    if variance == 0.0 {
+        return 0.0;
+    }
+    let s0 = expected_score(elo0);
+    let s1 = expected_score(elo1);
+    return n * (s1 - s0) * (2.0 * score - s0 - s1) / (2.0 * variance);
+}
+fn elo(wins: u32, draws: u32, losses: u32) -> f64 {
+    let n = (wins + draws + losses) as f64;
+    let score = ((wins as f64 + draws as f64 / 2.0) / n).clamp(0.001, 0.999);
+    return -400.0 * (1.0 / score - 1.0).log10();
+}
+fn arg_value<T: FromStr>(args: &[String], key: &str, default: T) -> T {
+    return match args.iter().position(|a| a == key) {
+        Some(i) => args
+            .get(i + 1)
+            .and_then(|v| v.parse::<T>().ok())
+            .unwrap_or(default),
+        None => default,
+    };
+}
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 3 {
+        println!("usage: sprt <new engine> <base engine> [nodes <n>] [movetime <ms>] [games <n>] [elo0 <elo>] [elo1 <elo>] [alpha <p>] [beta <p>]");
+        return;
+    }
+    let go = match args.iter().position(|a| a == "movetime") {
+        Some(_) => format!("go movetime {}", arg_value::<u64>(&args, "movetime", 100)),
+        None => format!("go nodes {}", arg_value::<u64>(&args, "nodes", 20000)),
+    };
+    let max_games = arg_value::<u32>(&args, "games", 20000);
+    let elo0 = arg_value::<f64>(&args, "elo0", 0.0);
+    let elo1 = arg_value::<f64>(&args, "elo1", 10.0);
+    let alpha = arg_value::<f64>(&args, "alpha", 0.05);
+    let beta = arg_value::<f64>(&args, "beta", 0.05);
+    let lower = (beta / (1.0 - alpha)).ln();
+    let upper = ((1.0 - beta) / alpha).ln();
+    let mut new = Engine::new(&args[1]);
+    let mut base = Engine::new(&args[2]);
+    let (mut wins, mut draws, mut losses) = (0, 0, 0);
+    let mut games = 0;
+    while games < max_games {
+        let opening = OPENINGS[(games as usize / 2) % OPENINGS.len()];
+        //results are counted from the new engine's perspective
+        let result = if games % 2 == 0 {
+            play_game(&mut new, &mut base, opening, &go)
+        } else {
+            match play_game(&mut base, &mut new, opening, &go) {
+                GameResult::Win => GameResult::Loss,
+                GameResult::Draw => GameResult::Draw,
+                GameResult::Loss => GameResult::Win,
+            }
+        };
+        match result {
+            GameResult::Win => wins += 1,
+            GameResult::Draw => draws += 1,
+            GameResult::Loss => losses += 1,
+        }
+        games += 1;
+        let ratio = llr(wins, draws, losses, elo0, elo1);
+        println!(
+            "games {} +{} ={} -{} elo {:.1} llr {:.2} ({:.2}, {:.2})",
+            games,
+            wins,
+            draws,
+            losses,
+            elo(wins, draws, losses),
+            ratio,
+            lower,
+            upper
+        );
+        if ratio >= upper {
+            println!("H1 accepted: the new build gains at least {} elo", elo1);
+            return;
+        }
+        if ratio <= lower {
+            println!("H0 accepted: the new build gains no more than {} elo", elo0);
+            return;
+        }
+    }
+    println!("no decision after {} games", games);
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn llr_decides_without_losses() {
+        let upper = (0.95f64 / 0.05).ln();
+        assert!(llr(30, 10, 0, 0.0, 20.0) >= upper);
+        assert!(llr(0, 10, 30, 0.0, 20.0) < 0.0);
+        assert_eq!(llr(0, 0, 0, 0.0, 20.0), 0.0);
+        assert_eq!(llr(0, 25, 0, 0.0, 20.0), 0.0);
+    }
+}
@@ -3,11 +3,14 @@ use std::str::FromStr;
 use chess::{BitBoard, Board, CastleRights, ChessMove, Color, Piece, Square};
 
 use crate::data::{get_pst_value, PAWN_ZOBRIST};
+use crate::score::Score;
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub struct Position {
     pub board: Board,
-    pst_values: i16,
+    pst_values: Score,
     pawn_hash: u64,
+    //plies since the last capture or pawn move, for the fifty move rule
+    halfmove_clock: u8,
 }
 impl Position {
     pub fn make_move_new(&self, mv: ChessMove) -> Position {
@@ -19,7 +22,7 @@ impl Position {
         let turn = self.board.side_to_move().to_index();
         let xturn = turn ^ 1;
         let promotion = mv.get_promotion();
-        let mut pst = 0;
+        let mut pst = Score::ZERO;
         if piece != Piece::Pawn && piece != Piece::King {
             pst += get_pst_value(turn, piece, to) - get_pst_value(turn, piece, from);
         }
@@ -29,6 +32,17 @@ impl Position {
         if is_capture && captured.unwrap() != Piece::Pawn {
             pst -= get_pst_value(xturn, captured.unwrap(), to);
         }
+        if piece == Piece::King && from.abs_diff(to) == 2 {
+            //castling, the rook jumps over the king. its square table score moves with it, else
+            //pst_values keeps scoring the rook on its corner for the rest of the game
+            let (rook_from, rook_to) = if to > from {
+                (from + 3, from + 1)
+            } else {
+                (from - 4, from - 1)
+            };
+            pst += get_pst_value(turn, Piece::Rook, rook_to)
+                - get_pst_value(turn, Piece::Rook, rook_from);
+        }
         let is_en_pessant = !is_capture
             && piece == Piece::Pawn
             && mv.get_dest().get_file() != mv.get_source().get_file();
@@ -49,14 +63,39 @@ impl Position {
             board: self.board.make_move_new(mv),
             pst_values: self.pst_values + pst,
             pawn_hash: self.pawn_hash ^ hash,
+            halfmove_clock: if piece == Piece::Pawn || is_capture {
+                0
+            } else {
+                self.halfmove_clock.saturating_add(1)
+            },
         };
     }
+    //passes the turn, None when in check. the halfmove clock is reset so that repetitions
+    //can't be detected across the null move
+    pub fn make_null_move(&self) -> Option<Position> {
+        return Some(Position {
+            board: self.board.null_move()?,
+            pst_values: self.pst_values,
+            pawn_hash: self.pawn_hash,
+            halfmove_clock: 0,
+        });
+    }
+    //false in king and pawn endings, where passing the turn isn't a safe assumption
+    pub fn has_non_pawn_material(&self, color: Color) -> bool {
+        return (self.board.color_combined(color).0
+            & !self.board.pieces(Piece::Pawn).0
+            & !self.board.pieces(Piece::King).0)
+            != 0;
+    }
     pub fn color_combined(&self, color: Color) -> &BitBoard {
         return self.board.color_combined(color);
     }
     pub fn get_hash(&self) -> u64 {
         return self.board.get_hash();
     }
+    pub fn halfmove_clock(&self) -> u8 {
+        return self.halfmove_clock;
+    }
     pub fn get_pawn_hash(&self) -> u64 {
         return self.pawn_hash;
     }
@@ -75,7 +114,7 @@ impl Position {
     pub fn king_square(&self, color: Color) -> Square {
         return self.board.king_square(color);
     }
-    pub fn get_pst_values(&self) -> i16 {
+    pub fn get_pst_values(&self) -> Score {
         return self.pst_values;
     }
     pub fn combined(&self) -> u64 {
@@ -87,7 +126,7 @@ impl Position {
     #[inline]
     pub fn new(fen: &str) -> Position {
         let board = Board::from_str(fen).unwrap();
-        let mut pst_values: i16 = 0;
+        let mut pst_values = Score::ZERO;
         let mut hash = 0;
         for color in 0..2 {
             let mut pieces = board
@@ -115,10 +154,17 @@ impl Position {
             board,
             pst_values,
             pawn_hash: hash,
+            halfmove_clock: fen
+                .split_whitespace()
+                .nth(4)
+                .and_then(|c| c.parse::<u8>().ok())
+                .unwrap_or(0),
         };
     }
+}
+impl Default for Position {
     #[inline]
-    pub fn default() -> Position {
+    fn default() -> Position {
         return Position::new("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
     }
 }
@@ -46,19 +46,22 @@ impl Book {
 #[folder = "src/files/"]
 #[prefix = ""]
 struct Asset;
-pub fn init_book_full() -> Book {
-    let file = Asset::get("Book.txt").unwrap();
+fn book_from_str(lines: &str) -> Book {
+    let book = lines
+        .split('\n')
+        .map(|l| l.trim_end().to_string())
+        .collect::<Vec<String>>();
     return Book {
-        book: std::str::from_utf8(file.data.as_ref())
-            .unwrap()
-            .split('\n')
-            .map(str::to_string)
-            .collect::<Vec<String>>(),
-        copy: std::str::from_utf8(file.data.as_ref())
-            .unwrap()
-            .split('\n')
-            .map(str::to_string)
-            .collect::<Vec<String>>(),
+        copy: book.clone(),
+        book,
         opening: "".to_string(),
     };
 }
+pub fn init_book_full() -> Book {
+    let file = Asset::get("Book.txt").unwrap();
+    return book_from_str(std::str::from_utf8(file.data.as_ref()).unwrap());
+}
+//loads a book with the same format as Book.txt, one game per line
+pub fn init_book_from_file(path: &str) -> std::io::Result<Book> {
+    return Ok(book_from_str(&std::fs::read_to_string(path)?));
+}
@@ -1,4 +1,6 @@
+use crate::score::Score;
 use chess::{Color, Piece, Square};
+use std::sync::Once;
 
 static mut DISTANCE_FROM_CENTER: [u8; 64] = [0; 64];
 static mut SQUARE_DISTANCE: [[i16; 64]; 64] = [[0; 64]; 64];
@@ -447,6 +449,8 @@ static mut FRONT_SPANS: [[u64; 64]; 2] = [[0; 64]; 2];
 static mut ADJACENT_FILES: [u64; 8] = [0; 8];
 static mut SUPPORTING_PAWNS: [u64; 64] = [0; 64];
 static mut FILESETS: [u64; 256] = [0; 256];
+//late move reductions indexed by [depth][move number]
+static mut LMR_TABLE: [[u8; 64]; 64] = [[0; 64]; 64];
 pub const ADJACENT_FILESETS: [u8; 8] = [
     0b11, 0b111, 0b1110, 0b11100, 0b111000, 0b1110000, 0b11100000, 0b11000000,
 ];
@@ -474,6 +478,8 @@ pub const DARK_SQUARES: u64 = 0xAA55AA55AA55AA55;
 pub const LIGHT_SQUARES: u64 = 0x55AA55AA55AA55AA;
 pub const SEVENTH_RANK: u64 = RANKS[6];
 pub const SECOND_RANK: u64 = RANKS[1];
+pub const FIRST_RANK: u64 = RANKS[0];
+pub const EIGHTH_RANK: u64 = RANKS[7];
 pub fn get_spst_value(color: Color, piece: Piece, square: Square) -> i8 {
     if piece == Piece::King || piece == Piece::Pawn {
         return 0;
@@ -482,12 +488,14 @@ pub fn get_spst_value(color: Color, piece: Piece, square: Square) -> i8 {
         return SORT_PIECE_SQ_TABLE[color.to_index()][piece.to_index()][square.to_index()];
     }
 }
-pub fn get_pst_value(color: usize, piece: Piece, square: usize) -> i16 {
-    return PIECE_SQUARE_TABLES[color][piece.to_index()][square];
-}
-pub fn calc_king_pst(color: usize, square: usize, endgame: f32, middle_game: f32) -> i16 {
-    return (PIECE_SQUARE_TABLES[color][5][square] as f32 * middle_game
-        + KING_SQUARE_TABLES[color][square] as f32 * endgame) as i16;
+//kings and pawns have their own endgame tables, the other pieces use the same value in both
+pub fn get_pst_value(color: usize, piece: Piece, square: usize) -> Score {
+    let mg = PIECE_SQUARE_TABLES[color][piece.to_index()][square];
+    return match piece {
+        Piece::King => Score::new(mg, KING_SQUARE_TABLES[color][square]),
+        Piece::Pawn => Score::new(mg, PAWN_SQUARE_TABLES[color][square]),
+        _ => Score::new(mg, mg),
+    };
 }
 pub fn get_distance_from_center(sq: usize) -> u8 {
     unsafe {
@@ -515,7 +523,18 @@ pub fn get_orthogonal_distance(sq1: usize, sq2: usize) -> u8 {
         return ORTHOGONAL_DISTANCE[sq1][sq2];
     }
 }
+//base reduction of a late quiet move, grows with both the depth and the move number
+pub fn get_lmr_reduction(depth: u8, move_number: usize) -> u8 {
+    unsafe {
+        return LMR_TABLE[(depth as usize).min(63)][move_number.min(63)];
+    }
+}
+//fills the tables above, it only does the work the first time it is called
 pub fn init() {
+    static INIT: Once = Once::new();
+    INIT.call_once(init_tables);
+}
+fn init_tables() {
     for file in 0..8 {
         for rank in 0..8 {
             let sq: usize = rank as usize * 8 + file as usize;
@@ -581,4 +600,13 @@ pub fn init() {
             }
         }
     }
+    let mut lmr_table = [[0; 64]; 64];
+    for (depth, row) in lmr_table.iter_mut().enumerate().skip(1) {
+        for (move_number, reduction) in row.iter_mut().enumerate().skip(1) {
+            *reduction = (0.75 + (depth as f32).ln() * (move_number as f32).ln() / 2.25) as u8;
+        }
+    }
+    unsafe {
+        LMR_TABLE = lmr_table;
+    }
 }
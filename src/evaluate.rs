@@ -1,126 +1,264 @@
 use crate::board::Position;
 use crate::data::{
-    calc_king_pst, get_adjacent_files, get_distance_from_center, get_fileset_bb, get_front_spans,
-    get_orthogonal_distance, ADJACENT_FILESETS, DARK_SQUARES, FILES, KING_ATTACKS_BITBOARD,
-    LIGHT_SQUARES, PAWN_SQUARE_TABLES, PIECE_SQUARE_TABLES, SECOND_RANK, SEVENTH_RANK,
+    get_adjacent_files, get_distance_from_center, get_fileset_bb, get_front_spans,
+    get_orthogonal_distance, get_pst_value, ADJACENT_FILESETS, DARK_SQUARES, EIGHTH_RANK, FILES,
+    FIRST_RANK, KING_ATTACKS_BITBOARD, LIGHT_SQUARES, SECOND_RANK, SEVENTH_RANK,
 };
 use crate::moves::{get_bishop_moves, get_knight_moves, get_rook_moves};
-use crate::transposition_table::TranspositionTable;
+use crate::score::{Score, MAX_PHASE};
+use crate::search::SearchStats;
+use crate::transposition_table::PawnTable;
 use chess::Color;
 use chess::{
-    CastleRights, Color::Black, Color::White, Piece::Bishop, Piece::Knight, Piece::Pawn,
-    Piece::Queen, Piece::Rook,
+    CastleRights, Color::Black, Color::White, Piece::Bishop, Piece::King, Piece::Knight,
+    Piece::Pawn, Piece::Queen, Piece::Rook,
 };
-pub static mut PAWN_TT_HITS: u32 = 0;
-const PAWN_VALUE: u32 = 100;
-const KNIGHT_VALUE: u32 = 310;
-const BISHOP_VALUE: u32 = 320;
-const ROOK_VALUE: u32 = 500;
-const QUEEN_VALUE: u32 = 975;
-const ENDGAME_MATERIAL_START: f32 = (ROOK_VALUE * 2 + BISHOP_VALUE + KNIGHT_VALUE) as f32;
-const MULTIPLIER: f32 = 1.0 / ENDGAME_MATERIAL_START as f32;
-const PASSED_PAWN_VALUES: [i16; 7] = [0, 90, 60, 40, 25, 15, 15];
-const BISHOP_PAIR_VALUE: i16 = 50;
-const UNHEALTHY_PAWN_PENALTY: i16 = 10;
-const OPEN_UNHEALTHY_PAWN_PENALTY: i16 = 10;
+const PAWN_VALUE: i16 = 100;
+const KNIGHT_VALUE: i16 = 310;
+const BISHOP_VALUE: i16 = 320;
+const ROOK_VALUE: i16 = 500;
+const QUEEN_VALUE: i16 = 975;
+//game phase of each piece, all of them on the board add up to score::MAX_PHASE
+const KNIGHT_PHASE: i32 = 1;
+const BISHOP_PHASE: i32 = 1;
+const ROOK_PHASE: i32 = 2;
+const QUEEN_PHASE: i32 = 4;
+const PASSED_PAWN_VALUES: [Score; 7] = [
+    Score::new(0, 0),
+    Score::new(90, 90),
+    Score::new(60, 60),
+    Score::new(40, 40),
+    Score::new(25, 25),
+    Score::new(15, 15),
+    Score::new(15, 15),
+];
+const BISHOP_PAIR_VALUE: Score = Score::new(50, 50);
+const UNHEALTHY_PAWN_PENALTY: Score = Score::new(10, 10);
+const OPEN_UNHEALTHY_PAWN_PENALTY: Score = Score::new(10, 10);
 const KING_SIDE_CASTLE_FILESET: u8 = ADJACENT_FILESETS[6];
 const QUEEN_SIDE_CASTLE_FILESET: u8 = ADJACENT_FILESETS[2];
 const PAWN_STORM_PENALTY: [i16; 8] = [0, 0, -60, -30, -10, 0, 0, 0];
-const BISHOP_MOBILITY_SCORE: i16 = 5;
-const KNIGHT_MOBILITY_SCORE: i16 = 4;
-const ROOK_MOBILITY_SCORE_ENDGAME: i16 = 4;
-const ROOK_MOBILITY_SCORE_MIDDLEGAME: i16 = 2;
-fn get_value<T>(m: T, e: T, endgame: f32) -> T {
-    if endgame == 0.0 {
-        return m;
-    }
-    return e;
-}
-fn get_endgame_weight(material: f32) -> f32 {
-    if material < ENDGAME_MATERIAL_START {
-        return 1.0 - (material * MULTIPLIER);
-    } else {
-        return 0.0;
-    };
-}
-fn evaluate_bishop_pair(bishops: u64) -> i16 {
+const BISHOP_MOBILITY_SCORE: Score = Score::new(5, 5);
+const KNIGHT_MOBILITY_SCORE: Score = Score::new(4, 4);
+const ROOK_MOBILITY_SCORE: Score = Score::new(2, 4);
+//rooks on closed and open files, open files next to or on the enemy king's file are worth more
+const ROOK_CLOSED_FILE: Score = Score::new(-10, -10);
+const ROOK_OPEN_FILE: Score = Score::new(10, 10);
+const ROOK_OPEN_FILE_NEAR_KING: Score = Score::new(20, 10);
+const ROOK_OPEN_FILE_ON_KING: Score = Score::new(30, 10);
+const ROOK_SEMI_OPEN_FILE_NEAR_KING: Score = Score::new(10, 0);
+const ROOK_SEMI_OPEN_FILE_ON_KING: Score = Score::new(20, 0);
+const ROOK_ON_SEVENTH: Score = Score::new(10, 30);
+const QUEEN_ON_SEVENTH: Score = Score::new(10, 20);
+const TEMPO: Score = Score::new(20, 10);
+fn evaluate_bishop_pair(bishops: u64) -> Score {
     if ((bishops & LIGHT_SQUARES) != 0) && ((bishops & DARK_SQUARES) != 0) {
         return BISHOP_PAIR_VALUE;
     }
-    return 0;
+    return Score::ZERO;
 }
-fn get_material(board: &Position, color: u64) -> (f32, i16) {
-    let material = (board.pieces(Knight) & color).count_ones() * KNIGHT_VALUE
-        + (board.pieces(Bishop) & color).count_ones() * BISHOP_VALUE
-        + (board.pieces(Rook) & color).count_ones() * ROOK_VALUE
-        + (board.pieces(Queen) & color).count_ones() * QUEEN_VALUE;
+//material without pawns, material with pawns and the phase of one side
+fn get_material(board: &Position, color: u64) -> (i16, Score, i32) {
+    let knights = (board.pieces(Knight) & color).count_ones() as i32;
+    let bishops = (board.pieces(Bishop) & color).count_ones() as i32;
+    let rooks = (board.pieces(Rook) & color).count_ones() as i32;
+    let queens = (board.pieces(Queen) & color).count_ones() as i32;
+    let pawns = (board.pieces(Pawn) & color).count_ones() as i32;
+    let material = knights as i16 * KNIGHT_VALUE
+        + bishops as i16 * BISHOP_VALUE
+        + rooks as i16 * ROOK_VALUE
+        + queens as i16 * QUEEN_VALUE;
     return (
-        material as f32,
-        (material + ((board.pieces(Pawn) & color).count_ones() * PAWN_VALUE)) as i16,
+        material,
+        Score::new(material, material) + Score::new(PAWN_VALUE, PAWN_VALUE) * pawns,
+        knights * KNIGHT_PHASE + bishops * BISHOP_PHASE + rooks * ROOK_PHASE + queens * QUEEN_PHASE,
     );
 }
-pub fn evaluate(board: &Position, tt: &mut TranspositionTable) -> i16 {
+//terms of the evaluation as printed by the eval command
+#[derive(Clone, Copy)]
+pub enum Term {
+    Material,
+    Pst,
+    Mobility,
+    PassedPawns,
+    IsolatedPawns,
+    DoubledPawns,
+    RookFiles,
+    SeventhRank,
+    BishopPair,
+    QueenTropism,
+    KingShield,
+    PawnStorm,
+    KingAttack,
+    MopUp,
+    Tempo,
+}
+pub const TERM_COUNT: usize = 15;
+const TERM_NAMES: [&str; TERM_COUNT] = [
+    "material",
+    "pst",
+    "mobility",
+    "passed pawns",
+    "isolated pawns",
+    "doubled pawns",
+    "rook files",
+    "seventh rank",
+    "bishop pair",
+    "queen tropism",
+    "king shield",
+    "pawn storm",
+    "king attack",
+    "mop up",
+    "tempo",
+];
+//every term of the evaluation for white and for black, each from that side's point of view
+pub struct EvalTrace {
+    pub terms: [[Score; 2]; TERM_COUNT],
+    pub phase: i32,
+}
+impl EvalTrace {
+    //white's score minus black's score, the same as evaluate gives from white's side
+    pub fn total(&self) -> Score {
+        return self
+            .terms
+            .iter()
+            .fold(Score::ZERO, |total, term| total + term[0] - term[1]);
+    }
+}
+//evaluates the position term by term with the same functions as evaluate, without the pawn table
+pub fn trace_evaluation(board: &Position) -> EvalTrace {
+    let colors = [White, Black];
+    let combined = colors.map(|c| board.color_combined(c).0);
+    let pawns = combined.map(|c| board.pieces(Pawn) & c);
+    let kings = colors.map(|c| board.king_square(c).to_index());
+    let materials = combined.map(|c| get_material(board, c));
+    let pawn_data = [
+        get_pawn_data(pawns[0], pawns[1], 0),
+        get_pawn_data(pawns[1], pawns[0], 1),
+    ];
+    let rook_files = get_rook_files(pawn_data[0].fileset, pawn_data[1].fileset);
+    let mobility = [
+        evaluate_mobility(board, KING_ATTACKS_BITBOARD[kings[1]], White),
+        evaluate_mobility(board, KING_ATTACKS_BITBOARD[kings[0]], Black),
+    ];
+    let mut trace = EvalTrace {
+        terms: [[Score::ZERO; 2]; TERM_COUNT],
+        phase: materials[0].2 + materials[1].2,
+    };
+    for c in 0..2 {
+        let them = c ^ 1;
+        //the square tables hold black's values negated
+        let mut pst = pawn_data[c].pst;
+        for piece in [Knight, Bishop, Rook, Queen, King] {
+            let mut bb = board.pieces(piece) & combined[c];
+            while bb != 0 {
+                pst += get_pst_value(c, piece, bb.trailing_zeros() as usize);
+                bb &= bb - 1;
+            }
+        }
+        let (shield, storm, attacked) =
+            evaluate_king_safety(board, colors[c], mobility[them].1, mobility[them].2);
+        let terms = &mut trace.terms;
+        terms[Term::Material as usize][c] = materials[c].1;
+        terms[Term::Pst as usize][c] = if c == 0 { pst } else { -pst };
+        terms[Term::Mobility as usize][c] = mobility[c].0;
+        terms[Term::PassedPawns as usize][c] = pawn_data[c].passed;
+        terms[Term::IsolatedPawns as usize][c] = pawn_data[c].isolated;
+        terms[Term::DoubledPawns as usize][c] = pawn_data[c].doubled;
+        terms[Term::RookFiles as usize][c] =
+            evaluate_rook_files(board.pieces(Rook) & combined[c], kings[them], rook_files[c]);
+        terms[Term::SeventhRank as usize][c] = evaluate_seventh_rank(board, colors[c]);
+        terms[Term::BishopPair as usize][c] =
+            evaluate_bishop_pair(board.pieces(Bishop) & combined[c]);
+        terms[Term::QueenTropism as usize][c] =
+            evaluate_queens(board.pieces(Queen) & combined[c], kings[them]);
+        terms[Term::KingShield as usize][c] = Score::new(shield, 0);
+        terms[Term::PawnStorm as usize][c] = Score::new(storm, 0);
+        terms[Term::KingAttack as usize][c] = Score::new(attacked, 0);
+        terms[Term::MopUp as usize][c] =
+            mop_up_eval(kings[c], kings[them], materials[c].0, materials[them].0);
+        if board.side_to_move() == colors[c] {
+            terms[Term::Tempo as usize][c] = TEMPO;
+        }
+    }
+    return trace;
+}
+pub fn print_evaluation(board: &Position) {
+    let trace = trace_evaluation(board);
+    println!(
+        "{:>14} | {:^13} | {:^13} | {:^13}",
+        "term", "white", "black", "total"
+    );
+    println!(
+        "{:>14} | {:>6} {:>6} | {:>6} {:>6} | {:>6} {:>6}",
+        "", "mg", "eg", "mg", "eg", "mg", "eg"
+    );
+    println!("{}", "-".repeat(62));
+    for (i, term) in trace.terms.iter().enumerate() {
+        let total = term[0] - term[1];
+        println!(
+            "{:>14} | {:>6} {:>6} | {:>6} {:>6} | {:>6} {:>6}",
+            TERM_NAMES[i],
+            term[0].mg(),
+            term[0].eg(),
+            term[1].mg(),
+            term[1].eg(),
+            total.mg(),
+            total.eg()
+        );
+    }
+    println!("{}", "-".repeat(62));
+    let total = trace.total();
+    println!(
+        "{:>14} | {:>13} | {:>13} | {:>6} {:>6}",
+        "total",
+        "",
+        "",
+        total.mg(),
+        total.eg()
+    );
+    println!(
+        "phase {}/{}, evaluation {} cp from white's side",
+        trace.phase.min(MAX_PHASE),
+        MAX_PHASE,
+        total.taper(trace.phase)
+    );
+}
+pub fn evaluate(board: &Position, pawn_table: &mut PawnTable, stats: &mut SearchStats) -> i16 {
     let white_combined = board.color_combined(White).0;
     let black_combined = board.color_combined(Black).0;
 
     let wp = board.pieces(Pawn) & white_combined;
     let bp = board.pieces(Pawn) & black_combined;
-    let wr = board.pieces(Rook) & white_combined;
-    let br = board.pieces(Rook) & black_combined;
     let wk = board.king_square(White).to_index();
     let bk = board.king_square(Black).to_index();
 
-    let (white_material_without_pawns, white_material) = get_material(board, white_combined);
-    let (black_material_without_pawns, black_material) = get_material(board, black_combined);
-
-    let white_endgame = get_endgame_weight(white_material_without_pawns);
-    let black_endgame = get_endgame_weight(black_material_without_pawns);
+    let (white_material_without_pawns, white_material, white_phase) =
+        get_material(board, white_combined);
+    let (black_material_without_pawns, black_material, black_phase) =
+        get_material(board, black_combined);
 
-    let white_middlegame = 1.0 - white_endgame;
-    let black_middlegame = 1.0 - black_endgame;
+    let piece_scores =
+        board.get_pst_values() + get_pst_value(0, King, wk) + get_pst_value(1, King, bk);
 
-    let piece_scores = board.get_pst_values()
-        + calc_king_pst(0, wk, black_endgame, black_middlegame)
-        + calc_king_pst(1, bk, white_endgame, white_middlegame);
-
-    let mop_eval: i16 = mop_up_eval(
+    let mop_eval = mop_up_eval(
         wk,
         bk,
         white_material_without_pawns,
         black_material_without_pawns,
-        black_endgame,
     ) - mop_up_eval(
         bk,
         wk,
         black_material_without_pawns,
         white_material_without_pawns,
-        white_endgame,
     );
 
-    let (pawn_eval, wp_fileset, bp_fileset) = evaluate_pawns(
-        tt,
-        board.get_pawn_hash(),
-        (white_endgame, black_endgame),
-        (white_middlegame, black_middlegame),
-        wp,
-        bp,
-    );
-    let closed = wp_fileset & bp_fileset;
-    let open = (!wp_fileset) & (!bp_fileset);
-    let semi_open_white = bp_fileset & (!wp_fileset);
-    let semi_open_black = wp_fileset & (bp_fileset);
+    let (pawn_eval, wp_fileset, bp_fileset) =
+        evaluate_pawns(pawn_table, stats, board.get_pawn_hash(), wp, bp);
+    let [white_files, black_files] = get_rook_files(wp_fileset, bp_fileset);
 
-    let rooks_eval = evaluate_rooks(
-        wr,
-        br,
-        get_fileset_bb(open),
-        get_fileset_bb(semi_open_white),
-        get_fileset_bb(semi_open_black),
-        get_fileset_bb(closed),
-        wk,
-        bk,
-        (white_endgame, black_endgame),
-    );
+    let rooks_eval = evaluate_rook_files(board.pieces(Rook) & white_combined, bk, white_files)
+        - evaluate_rook_files(board.pieces(Rook) & black_combined, wk, black_files);
 
     let bishop_eval = evaluate_bishop_pair(board.pieces(Bishop) & white_combined)
         - evaluate_bishop_pair(board.pieces(Bishop) & black_combined);
@@ -128,44 +266,28 @@ pub fn evaluate(board: &Position, tt: &mut TranspositionTable) -> i16 {
     let queens_eval = evaluate_queens(board.pieces(Queen) & white_combined, bk)
         - evaluate_queens(board.pieces(Queen) & black_combined, wk);
 
-    let seventh_rank_value = if (bp & SEVENTH_RANK) != 0 || bk > 55 {
-        seventh_rank_bounus(
-            board.pieces(Queen) & white_combined & SEVENTH_RANK,
-            board.pieces(Rook) & white_combined & SEVENTH_RANK,
-            black_endgame,
-        )
-    } else {
-        0
-    } - if (wp & SECOND_RANK) != 0 || wk < 8 {
-        seventh_rank_bounus(
-            board.pieces(Queen) & black_combined & SECOND_RANK,
-            board.pieces(Rook) & black_combined & SECOND_RANK,
-            white_endgame,
-        )
-    } else {
-        0
-    };
+    let seventh_rank_value =
+        evaluate_seventh_rank(board, White) - evaluate_seventh_rank(board, Black);
+
     let (white_mobility_score, white_attack_count, white_attack_value) =
-        evaluate_mobility(board, KING_ATTACKS_BITBOARD[bk], White, black_endgame);
+        evaluate_mobility(board, KING_ATTACKS_BITBOARD[bk], White);
     let (black_mobility_score, black_attack_count, black_attack_value) =
-        evaluate_mobility(board, KING_ATTACKS_BITBOARD[wk], Black, white_endgame);
+        evaluate_mobility(board, KING_ATTACKS_BITBOARD[wk], Black);
+
+    let (white_shield, white_storm, white_attacked) =
+        evaluate_king_safety(board, White, black_attack_count, black_attack_value);
+    let (black_shield, black_storm, black_attacked) =
+        evaluate_king_safety(board, Black, white_attack_count, white_attack_value);
+    let king_eval =
+        white_shield + white_storm + white_attacked - (black_shield + black_storm + black_attacked);
 
-    let king_eval = if black_endgame != 0.0 && (board.pieces(Queen) & black_combined) != 0 {
-        evaluate_king_safety(wp, bp, wk, 0, board, black_attack_count, black_attack_value)
-    } else {
-        0
-    } - if white_endgame != 0.0 && (board.pieces(Queen) & white_combined) != 0 {
-        evaluate_king_safety(bp, wp, bk, 1, board, white_attack_count, white_attack_value)
-    } else {
-        0
-    };
     let tempo_bounus = if board.side_to_move() == White {
-        get_value(20, 10, black_endgame)
+        TEMPO
     } else {
-        get_value(-20, -10, white_endgame)
+        -TEMPO
     };
 
-    let eval = white_material - black_material + white_mobility_score - black_mobility_score
+    let score = white_material - black_material + white_mobility_score - black_mobility_score
         + mop_eval
         + piece_scores
         + pawn_eval
@@ -174,152 +296,153 @@ pub fn evaluate(board: &Position, tt: &mut TranspositionTable) -> i16 {
         + queens_eval
         + seventh_rank_value
         + tempo_bounus
-        + king_eval;
+        + Score::new(king_eval, 0);
+    let eval = score.taper(white_phase + black_phase);
     if board.side_to_move() == White {
         return eval;
     }
     return -eval;
 }
-fn mop_up_eval(
-    my_king: usize,
-    their_king: usize,
-    my_material: f32,
-    their_material: f32,
-    endgame: f32,
-) -> i16 {
+//drives the losing king to the edge and the winning king towards it, an endgame only term
+fn mop_up_eval(my_king: usize, their_king: usize, my_material: i16, their_material: i16) -> Score {
     let mut score: i16 = 0;
-    if my_material > (their_material + 200.0) && endgame > 0.0 {
+    if my_material > (their_material + 200) {
         score += get_distance_from_center(their_king) as i16 * 10;
         score += (14 - get_orthogonal_distance(my_king, their_king) as i16) * 4
     }
-    return score;
+    return Score::new(0, score);
 }
-fn get_pawn_data(pawns: u64, enemy_pawns: u64, color: usize) -> (i16, u8, i16, i16) {
-    let mut score = 0;
+//pawn terms of one side, all of them but the square table score are for that side
+struct PawnData {
+    passed: Score,
+    isolated: Score,
+    doubled: Score,
+    pst: Score,
+    fileset: u8,
+}
+impl PawnData {
+    fn structure(&self) -> Score {
+        return self.passed + self.isolated + self.doubled;
+    }
+}
+fn get_pawn_data(pawns: u64, enemy_pawns: u64, color: usize) -> PawnData {
+    let mut data = PawnData {
+        passed: Score::ZERO,
+        isolated: Score::ZERO,
+        doubled: Score::ZERO,
+        pst: Score::ZERO,
+        fileset: 0,
+    };
     let mut p = pawns;
-    let mut fileset: u8 = 0;
-    let mut middle_game = 0;
-    let mut endgame = 0;
     while p != 0 {
         let i = p.trailing_zeros() as usize;
         p &= p - 1;
         let file = i & 7;
-        endgame += PAWN_SQUARE_TABLES[color][i];
-        middle_game += PIECE_SQUARE_TABLES[color][0][i];
+        data.pst += get_pst_value(color, Pawn, i);
         let front_span = get_front_spans(color, i) & enemy_pawns;
         let is_open = front_span & FILES[file] == 0;
-        if ((fileset >> file) & 1) == 1 {
+        if ((data.fileset >> file) & 1) == 1 {
             //doubled pawn
-            score -= if is_open {
+            data.doubled -= if is_open {
                 OPEN_UNHEALTHY_PAWN_PENALTY
             } else {
                 UNHEALTHY_PAWN_PENALTY
             };
         } else {
-            fileset |= 1 << file;
+            data.fileset |= 1 << file;
         }
         if front_span == 0 {
             //passer
             let rank = (i >> 3) as usize;
-            score += PASSED_PAWN_VALUES[if color == 0 { 7 - rank } else { rank }]
+            data.passed += PASSED_PAWN_VALUES[if color == 0 { 7 - rank } else { rank }]
         }
         if (get_adjacent_files(file) & pawns) == 0 {
             //isolated pawn
-            score -= if is_open {
+            data.isolated -= if is_open {
                 OPEN_UNHEALTHY_PAWN_PENALTY
             } else {
                 UNHEALTHY_PAWN_PENALTY
             };
         }
     }
-    return (score, fileset, middle_game, endgame);
+    return data;
 }
 fn evaluate_pawns(
-    tt: &mut TranspositionTable,
+    pawn_table: &mut PawnTable,
+    stats: &mut SearchStats,
     hash: u64,
-    endgame: (f32, f32),
-    middle_game: (f32, f32),
     wp: u64,
     bp: u64,
-) -> (i16, u8, u8) {
-    let entry = tt.look_up_pawn_structure(hash);
+) -> (Score, u8, u8) {
+    let entry = pawn_table.look_up_pawn_structure(hash);
     if entry.is_some() {
-        unsafe { PAWN_TT_HITS += 1 }
+        stats.pawn_tt_hits += 1;
         let pawn_data = entry.unwrap();
-        let score = pawn_data.eval
-            + (pawn_data.w_pst.0 as f32 * middle_game.1
-                + pawn_data.w_pst.1 as f32 * endgame.1
-                + pawn_data.b_pst.0 as f32 * middle_game.0
-                + pawn_data.b_pst.1 as f32 * endgame.0) as i16;
-        return (score, pawn_data.w_filesets, pawn_data.b_filesets);
-    } else {
-        let w_data = get_pawn_data(wp, bp, 0);
-        let b_data = get_pawn_data(bp, wp, 1);
-        let score = w_data.0 - b_data.0
-            + (w_data.1 as f32 * middle_game.1
-                + w_data.2 as f32 * endgame.1
-                + b_data.1 as f32 * middle_game.0
-                + b_data.2 as f32 * endgame.0) as i16;
-        tt.set_pawn_struct(
-            hash,
-            w_data.1,
-            b_data.1,
-            (b_data.2, b_data.3),
-            (w_data.2, w_data.3),
-            w_data.0 - b_data.0,
-        );
-        return (score, w_data.1, b_data.1);
-    };
+        return (pawn_data.eval, pawn_data.w_filesets, pawn_data.b_filesets);
+    }
+    let w_data = get_pawn_data(wp, bp, 0);
+    let b_data = get_pawn_data(bp, wp, 1);
+    let score = w_data.structure() - b_data.structure() + w_data.pst + b_data.pst;
+    pawn_table.set_pawn_struct(hash, w_data.fileset, b_data.fileset, score);
+    return (score, w_data.fileset, b_data.fileset);
 }
-fn evaluate_rooks(
-    wr: u64,
-    br: u64,
+//open, semi open and closed files as seen by the rooks of one side
+#[derive(Clone, Copy)]
+struct RookFiles {
     open: u64,
-    semi_open_white: u64,
-    semi_open_black: u64,
+    semi_open: u64,
     closed: u64,
-    wk: usize,
-    bk: usize,
-    endgame: (f32, f32),
-) -> i16 {
-    let mut score = 0;
-    let w_adjacent = get_adjacent_files(wk & 7) & br;
-    let b_adjacent = get_adjacent_files(bk & 7) & wr;
-    let w_file = FILES[wk & 7] & br;
-    let b_file = FILES[bk & 7] & wr;
-    //closed files: -10
-    score -= (closed & wr).count_ones() as i16 * 10 - (closed & br).count_ones() as i16 * 10;
-
-    //open file
-    score += (open & wr).count_ones() as i16 * 10
-        + (open & b_adjacent).count_ones() as i16 * get_value(20, 10, endgame.1)
-        + (open & b_file).count_ones() as i16 * get_value(30, 10, endgame.1);
-
-    score -= (open & br).count_ones() as i16 * 10
-        + (open & w_adjacent).count_ones() as i16 * get_value(20, 10, endgame.0)
-        + (open & w_file).count_ones() as i16 * get_value(20, 10, endgame.0);
-
-    if endgame.1 == 0.0 {
-        score += (semi_open_white & b_adjacent).count_ones() as i16 * 10
-            + (semi_open_white & b_file).count_ones() as i16 * 20;
-    }
-    if endgame.0 == 0.0 {
-        score -= (semi_open_black & w_adjacent).count_ones() as i16 * 10
-            + (semi_open_black & w_file).count_ones() as i16 * 20;
-    }
-    return score;
 }
-fn evaluate_queens(mut queens: u64, their_king: usize) -> i16 {
+fn get_rook_files(wp_fileset: u8, bp_fileset: u8) -> [RookFiles; 2] {
+    let open = get_fileset_bb((!wp_fileset) & (!bp_fileset));
+    let closed = get_fileset_bb(wp_fileset & bp_fileset);
+    return [
+        RookFiles {
+            open,
+            semi_open: get_fileset_bb(bp_fileset & (!wp_fileset)),
+            closed,
+        },
+        RookFiles {
+            open,
+            semi_open: get_fileset_bb(wp_fileset & (!bp_fileset)),
+            closed,
+        },
+    ];
+}
+fn evaluate_rook_files(rooks: u64, their_king: usize, files: RookFiles) -> Score {
+    let count = |bb: u64| bb.count_ones() as i32;
+    let adjacent = get_adjacent_files(their_king & 7) & rooks;
+    let king_file = FILES[their_king & 7] & rooks;
+    return ROOK_CLOSED_FILE * count(files.closed & rooks)
+        + ROOK_OPEN_FILE * count(files.open & rooks)
+        + ROOK_OPEN_FILE_NEAR_KING * count(files.open & adjacent)
+        + ROOK_OPEN_FILE_ON_KING * count(files.open & king_file)
+        + ROOK_SEMI_OPEN_FILE_NEAR_KING * count(files.semi_open & adjacent)
+        + ROOK_SEMI_OPEN_FILE_ON_KING * count(files.semi_open & king_file);
+}
+fn evaluate_queens(mut queens: u64, their_king: usize) -> Score {
     let mut score = 0;
     while queens != 0 {
         score += 10 - get_orthogonal_distance(queens.trailing_zeros() as usize, their_king) as i16;
         queens &= queens - 1;
     }
-    return score;
+    return Score::new(score, score);
 }
-fn seventh_rank_bounus(queens: u64, rooks: u64, endgame: f32) -> i16 {
-    return (rooks.count_ones() * get_value(10, 30, endgame)
-        + queens.count_ones() * get_value(10, 20, endgame)) as i16;
+//rooks and queens on the 7th rank, only while there are enemy pawns on it or the enemy king is
+//stuck on the 8th
+fn evaluate_seventh_rank(board: &Position, color: Color) -> Score {
+    let us = board.color_combined(color).0;
+    let them = board.color_combined(!color).0;
+    let (seventh, eighth) = if color == White {
+        (SEVENTH_RANK, EIGHTH_RANK)
+    } else {
+        (SECOND_RANK, FIRST_RANK)
+    };
+    if (board.pieces(Pawn) & them & seventh) == 0 && (board.pieces(King) & them & eighth) == 0 {
+        return Score::ZERO;
+    }
+    return ROOK_ON_SEVENTH * (board.pieces(Rook) & us & seventh).count_ones() as i32
+        + QUEEN_ON_SEVENTH * (board.pieces(Queen) & us & seventh).count_ones() as i32;
 }
 fn evaluate_pawn_shield(pawns: u64, king: usize, color: usize) -> i16 {
     let mut score = 0;
@@ -373,88 +496,79 @@ fn evaluate_pawn_storm(their_pawns: u64, mut fileset: u8, color: usize) -> i16 {
     }
     return score;
 }
-fn evaluate_mobility(
-    board: &Position,
-    targets: u64,
-    color: Color,
-    endgame: f32,
-) -> (i16, u32, f32) {
+fn evaluate_mobility(board: &Position, targets: u64, color: Color) -> (Score, u32, i32) {
     let color_combined = board.color_combined(color).0;
     let mut attacking_piece_count = 0;
-    let mut attacking_piece_values = 0.0;
+    let mut attacking_piece_values = 0;
     let blockers = board.combined();
 
-    let mut score = 0;
+    let mut score = Score::ZERO;
 
     let mut knights = board.pieces(Knight) & color_combined;
     let mut knight_moves = 0;
     while knights != 0 {
         let moves = get_knight_moves(knights.trailing_zeros() as usize);
-        knight_moves += (moves & !blockers).count_ones() as i16;
+        knight_moves += (moves & !blockers).count_ones() as i32;
         if moves & targets != 0 {
             attacking_piece_count += 1;
-            attacking_piece_values += 1.0;
+            attacking_piece_values += 1;
         }
         knights &= knights - 1;
     }
     if knight_moves != 0 {
-        score += (knight_moves - 4) * KNIGHT_MOBILITY_SCORE;
+        score += KNIGHT_MOBILITY_SCORE * (knight_moves - 4);
     }
 
     let mut bishops = board.pieces(Bishop) & color_combined;
     let mut bishop_moves = 0;
     while bishops != 0 {
         let moves = get_bishop_moves(bishops.trailing_zeros() as usize, blockers);
-        bishop_moves += (moves & !blockers).count_ones() as i16;
+        bishop_moves += (moves & !blockers).count_ones() as i32;
         if moves & targets != 0 {
             attacking_piece_count += 1;
-            attacking_piece_values += 1.0;
+            attacking_piece_values += 1;
         }
         bishops &= bishops - 1;
     }
     if bishop_moves != 0 {
-        score += (bishop_moves - 6) * BISHOP_MOBILITY_SCORE;
+        score += BISHOP_MOBILITY_SCORE * (bishop_moves - 6);
     }
     let mut rooks = board.pieces(Rook) & color_combined;
     let mut rook_moves = 0;
     while rooks != 0 {
         let moves = get_rook_moves(rooks.trailing_zeros() as usize, blockers);
-        rook_moves += (moves & !blockers).count_ones() as i16;
+        rook_moves += (moves & !blockers).count_ones() as i32;
         if moves & targets != 0 {
             attacking_piece_count += 1;
-            attacking_piece_values += 2.0;
+            attacking_piece_values += 2;
         }
         rooks &= rooks - 1;
     }
     if rook_moves != 0 {
-        score += (rook_moves - 7)
-            * get_value(
-                ROOK_MOBILITY_SCORE_MIDDLEGAME,
-                ROOK_MOBILITY_SCORE_ENDGAME,
-                endgame,
-            );
+        score += ROOK_MOBILITY_SCORE * (rook_moves - 7);
     }
 
     return (score, attacking_piece_count, attacking_piece_values);
 }
-fn get_piece_attack_weight(num: u32) -> f32 {
+//percent of the attack value that counts, a lone attacker is harmless
+fn get_piece_attack_weight(num: u32) -> i32 {
     match num {
-        0 => 0.0,
-        1 => 0.0,
-        2 => 0.5,
-        3 => 0.75,
-        4 => 0.88,
-        5 => 0.94,
-        6 => 0.97,
-        7 => 0.99,
-        _ => 1.0,
+        0 => 0,
+        1 => 0,
+        2 => 50,
+        3 => 75,
+        4 => 88,
+        5 => 94,
+        6 => 97,
+        7 => 99,
+        _ => 100,
     }
 }
 fn evaluate_piece_attacks(
     board: &Position,
     targets: u64,
     mut count: u32,
-    mut values: f32,
+    mut values: i32,
     color: Color,
 ) -> i16 {
     let color_combined = board.color_combined(color).0;
@@ -466,21 +580,29 @@ fn evaluate_piece_attacks(
             || (get_bishop_moves(q, blockers) & targets != 0)
         {
             count += 1;
-            values += 4.0;
+            values += 4;
         }
         queens &= queens - 1;
     }
-    return (20.0 * values * get_piece_attack_weight(count)) as i16;
+    return (20 * values * get_piece_attack_weight(count) / 100) as i16;
 }
+//pawn shield, pawn storm and piece attack scores of one side's king, they only count while the
+//attacker still has a queen
 fn evaluate_king_safety(
-    my_pawns: u64,
-    their_pawns: u64,
-    king: usize,
-    color: usize,
     board: &Position,
+    color: Color,
     attacking_pieces_count: u32,
-    attacking_pieces_value: f32,
-) -> i16 {
+    attacking_pieces_value: i32,
+) -> (i16, i16, i16) {
+    let us = board.color_combined(color).0;
+    let them = board.color_combined(!color).0;
+    if (board.pieces(Queen) & them) == 0 {
+        return (0, 0, 0);
+    }
+    let my_pawns = board.pieces(Pawn) & us;
+    let their_pawns = board.pieces(Pawn) & them;
+    let king = board.king_square(color).to_index();
+    let color = color.to_index();
     let castling_rights = board.castle_rights(if color == 0 { White } else { Black });
     let mut storm_value = evaluate_pawn_storm(their_pawns, ADJACENT_FILESETS[king & 7], color);
     if castling_rights != CastleRights::NoRights {
@@ -496,12 +618,58 @@ fn evaluate_king_safety(
         };
         storm_value = (storm_value + value) / 2;
     }
-    return evaluate_pawn_shield(my_pawns, king, color) + storm_value
-        - evaluate_piece_attacks(
+    return (
+        evaluate_pawn_shield(my_pawns, king, color),
+        storm_value,
+        -evaluate_piece_attacks(
             board,
             KING_ATTACKS_BITBOARD[king],
             attacking_pieces_count,
             attacking_pieces_value,
             if color == 0 { Black } else { White },
-        );
+        ),
+    );
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::init;
+    use chess::ChessMove;
+    use std::str::FromStr;
+    //positions reached by these moves go through make_move_new, so the square table scores are
+    //the incrementally updated ones, castling on both wings included
+    const GAMES: [(&str, &str); 4] = [
+        (
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "e2e4 e7e5 g1f3 b8c6 f1c4 g8f6 e1g1 f8c5 d2d3 e8g8 c1g5 h7h6 g5f6 d8f6",
+        ),
+        (
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "d2d4 d7d5 b1c3 b8c6 c1f4 c8f5 d1d2 d8d7 e1c1 e8c8 c3d5 d7d5",
+        ),
+        (
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "e1c1 e8g8 d5e6 h3g2 e6f7 g2h1q",
+        ),
+        ("8/5k2/3p4/1p1Pp2p/pP2Pp1P/P4P1K/8/8 b - - 99 50", "f7e7 h3g2"),
+    ];
+    #[test]
+    fn trace_total_matches_evaluate() {
+        init();
+        let mut pawn_table = PawnTable::init(1);
+        let mut stats = SearchStats::default();
+        for (fen, moves) in GAMES {
+            let mut board = Position::new(fen);
+            for m in moves.split_whitespace() {
+                board = board.make_move_new(ChessMove::from_str(m).unwrap());
+                let trace = trace_evaluation(&board);
+                let mut traced = trace.total().taper(trace.phase);
+                if board.side_to_move() == Black {
+                    traced = -traced;
+                }
+                let eval = evaluate(&board, &mut pawn_table, &mut stats);
+                assert_eq!(traced, eval, "{} after {}", fen, m);
+            }
+        }
+    }
 }
@@ -0,0 +1,119 @@
+use chess::{ChessMove, Piece, Square};
+const KILLERS_PER_PLY: usize = 3;
+const KILLER_PLIES: usize = 20;
+//history scores stay within plus or minus this, see update
+pub const MAX_HISTORY: i32 = 16384;
+pub type Killers = [ChessMove; KILLERS_PER_PLY];
+//the piece and destination of an earlier move on the current line
+pub type Continuation = Option<(Piece, Square)>;
+//indexed by [prev piece][prev to][piece][to]
+type ContinuationHistory = Vec<[[[i16; 64]; 6]; 64]>;
+//gravity update: the bonus shrinks as the score approaches the bound, so scores never leave
+//the range and old information fades instead of saturating
+fn gravity(value: i32, bonus: i32) -> i32 {
+    let bonus = bonus.clamp(-MAX_HISTORY, MAX_HISTORY);
+    return value + bonus - value * bonus.abs() / MAX_HISTORY;
+}
+//history bonus for a move that caused a cutoff at the given depth, the rest get it as a malus
+pub fn history_bonus(depth: u8) -> i32 {
+    return (16 * depth as i32 * depth as i32).min(2000);
+}
+//move ordering heuristics, every search thread has its own
+pub struct History {
+    killers: [Killers; KILLER_PLIES],
+    pub default_killers: Killers,
+    //butterfly history, indexed by [color][piece][to]
+    pub history: [[[i32; 64]; 6]; 2],
+    //continuation histories of the move one and two plies ago
+    continuation: [ContinuationHistory; 2],
+    //indexed by [piece][to][captured]
+    capture_history: [[[i16; 6]; 64]; 6],
+    pub counters: [[[ChessMove; 64]; 6]; 2],
+}
+impl Default for History {
+    fn default() -> History {
+        return History::new();
+    }
+}
+impl History {
+    pub fn new() -> History {
+        return History {
+            killers: [[ChessMove::default(); KILLERS_PER_PLY]; KILLER_PLIES],
+            default_killers: [ChessMove::default(); KILLERS_PER_PLY],
+            history: [[[0; 64]; 6]; 2],
+            continuation: [vec![[[[0; 64]; 6]; 64]; 6], vec![[[[0; 64]; 6]; 64]; 6]],
+            capture_history: [[[0; 6]; 64]; 6],
+            counters: [[[ChessMove::default(); 64]; 6]; 2],
+        };
+    }
+    pub fn clear(&mut self) {
+        *self = History::new();
+    }
+    pub fn get_killers(&self, ply: usize) -> &Killers {
+        if ply >= KILLER_PLIES {
+            return &self.default_killers;
+        }
+        return &self.killers[ply];
+    }
+    pub fn store_killer(
+        &mut self,
+        ply: usize,
+        mv: ChessMove,
+        color: usize,
+        prev_piece: Piece,
+        prev_sq: Square,
+    ) {
+        //set move for counter move heuristic
+        self.counters[color][prev_piece.to_index()][prev_sq.to_index()] = mv;
+        if ply >= KILLER_PLIES || self.killers[ply].contains(&mv) {
+            return;
+        }
+        //shifted from the back so every older killer moves down one slot
+        for i in (1..KILLERS_PER_PLY).rev() {
+            self.killers[ply][i] = self.killers[ply][i - 1];
+        }
+        self.killers[ply][0] = mv;
+    }
+    //butterfly and continuation history of a quiet move summed, for ordering and reductions
+    pub fn quiet_score(
+        &self,
+        color: usize,
+        piece: Piece,
+        to: Square,
+        continuations: &[Continuation; 2],
+    ) -> i32 {
+        let mut score = self.history[color][piece.to_index()][to.to_index()];
+        for (table, cont) in self.continuation.iter().zip(continuations) {
+            if let Some((prev_piece, prev_to)) = cont {
+                score += table[prev_piece.to_index()][prev_to.to_index()][piece.to_index()]
+                    [to.to_index()] as i32;
+            }
+        }
+        return score;
+    }
+    pub fn update_quiet(
+        &mut self,
+        color: usize,
+        piece: Piece,
+        to: Square,
+        continuations: &[Continuation; 2],
+        bonus: i32,
+    ) {
+        let entry = &mut self.history[color][piece.to_index()][to.to_index()];
+        *entry = gravity(*entry, bonus);
+        for (table, cont) in self.continuation.iter_mut().zip(continuations) {
+            if let Some((prev_piece, prev_to)) = cont {
+                let entry = &mut table[prev_piece.to_index()][prev_to.to_index()][piece.to_index()]
+                    [to.to_index()];
+                *entry = gravity(*entry as i32, bonus) as i16;
+            }
+        }
+    }
+    pub fn capture_score(&self, piece: Piece, to: Square, captured: Piece) -> i32 {
+        return self.capture_history[piece.to_index()][to.to_index()][captured.to_index()] as i32;
+    }
+    pub fn update_capture(&mut self, piece: Piece, to: Square, captured: Piece, bonus: i32) {
+        let entry = &mut self.capture_history[piece.to_index()][to.to_index()][captured.to_index()];
+        *entry = gravity(*entry as i32, bonus) as i16;
+    }
+}
@@ -0,0 +1,18 @@
+//the engine as a library, the uci binary and anything else that wants to search or evaluate
+//a position use it through these modules. data::init has to run once before anything else,
+//e.g. evaluate::trace_evaluation for a per term breakdown of the evaluation
+pub mod board;
+pub mod board_util;
+pub mod book;
+pub mod data;
+pub mod evaluate;
+pub mod history;
+pub mod magics;
+pub mod moves;
+pub mod options;
+pub mod perft;
+pub mod score;
+pub mod search;
+pub mod time_manager;
+pub mod transposition_table;
+pub mod uci;
@@ -1,16 +1,5 @@
-mod board;
-mod board_util;
-mod book;
-mod data;
-mod evaluate;
-mod magics;
-mod moves;
-mod perft;
-mod search;
-mod transposition_table;
-mod uci;
-use data::init;
-use uci::uci;
+use ruchess::data::init;
+use ruchess::uci::uci;
 //file at  target\<debug|release>\app.exe,
 fn main() {
     init();
@@ -9,13 +9,21 @@ const MVV_LVA: [[i32; 6]; 5] = [
 ];
 const ROOK: usize = 0;
 const BISHOP: usize = 1;
+//piece values for the static exchange evaluation, indexed by Piece::to_index
+pub const SEE_VALUES: [i16; 6] = [100, 300, 300, 500, 900, 20000];
+//first and eighth rank, where pawns promote
+const LAST_RANKS: u64 = 0xff000000000000ff;
+use crate::data::get_spst_value;
 use crate::data::KNIGHT_MOVES;
+use crate::history::{Continuation, History, Killers};
 use crate::magics::{MAGIC_NUMBERS, MOVES, RAYS};
-use crate::{data::get_spst_value, transposition_table::Killers};
-use chess::{Board, ChessMove, Color, MoveGen, Piece};
+use chess::{
+    between, get_king_moves, line, BitBoard, Board, ChessMove, Color, MoveGen, Piece, Square,
+    ALL_PIECES, EMPTY,
+};
 #[derive(PartialEq, PartialOrd, Eq, Ord, Clone, Copy)]
 pub enum MoveType {
-    BadCapture, //captures with a piece of higher value that can be recaptured by a pawn
+    BadCapture, //captures that lose material according to the static exchange evaluation
     QuietMove,
     CounterMove,
     KillerMove,
@@ -23,16 +31,6 @@ pub enum MoveType {
     Promotion,
     HashMove,
 }
-fn piece_value(piece: Piece) -> i8 {
-    match piece {
-        Piece::Pawn => 1,
-        Piece::King => 0,
-        Piece::Bishop => 3,
-        Piece::Knight => 3,
-        Piece::Rook => 5,
-        Piece::Queen => 9,
-    }
-}
 fn promotion_value(piece: Piece) -> i32 {
     match piece {
         Piece::Pawn => 0,
@@ -43,160 +41,632 @@ fn promotion_value(piece: Piece) -> i32 {
         Piece::Queen => 32,
     }
 }
-fn move_type(
-    piece_at_start: Piece,
-    piece_at_end: Option<Piece>,
-    is_controled: bool,
-    is_tt_move: bool,
-    is_killer: bool,
-    is_counter: bool,
-    is_promo: bool,
-) -> MoveType {
-    if is_tt_move {
-        return MoveType::HashMove;
-    }
-    if is_promo {
-        return MoveType::Promotion;
-    }
-    if piece_at_end.is_some() {
-        if is_controled && piece_value(piece_at_start) > piece_value(piece_at_end.unwrap()) {
-            return MoveType::BadCapture;
-        }
-        return MoveType::GoodCapture;
-    }
-    if is_killer {
-        return MoveType::KillerMove;
-    }
-    if is_counter {
-        return MoveType::CounterMove;
-    }
-    return MoveType::QuietMove;
-}
-fn move_value(
-    m: &ChessMove,
-    piece_at_start: Piece,
-    piece_at_end: Option<Piece>,
-    is_controled: bool,
-    color: Color,
-    is_tt_move: bool,
-    is_killer: bool,
-    is_counter: bool,
-    history_value: i32,
-) -> (ChessMove, i32, MoveType) {
-    let mt = move_type(
-        piece_at_start,
-        piece_at_end,
-        is_controled,
-        is_tt_move,
-        is_killer,
-        is_counter,
-        m.get_promotion().is_some(),
-    );
-    let mut value = 0;
-    if piece_at_end.is_some() {
-        //captures sorted with MVV_LVA
-        value += MVV_LVA[piece_at_end.unwrap().to_index()][piece_at_start.to_index()];
-    } else {
-        //quiets sorter with history heuristic
-        if is_controled && piece_at_start != Piece::Pawn {
-            value -= 1000000;
-        }
-        value += history_value;
-    }
-
-    if m.get_promotion().is_some() {
-        value += promotion_value(m.get_promotion().unwrap());
-    } else {
-        value += (get_spst_value(color, piece_at_start, m.get_dest())
-            - get_spst_value(color, piece_at_start, m.get_source())) as i32;
-    }
-    return (*m, value, mt);
-}
-pub fn sort_moves(
-    iterable: &mut MoveGen,
+//quiets sorted with the butterfly and continuation histories, moves to squares attacked by
+//enemy pawns last
+fn quiet_value(
     board: &Board,
+    mv: ChessMove,
+    controled: u64,
+    history: &History,
+    continuations: &[Continuation; 2],
+) -> i32 {
+    let piece = board.piece_on(mv.get_source()).unwrap();
+    let color = board.side_to_move();
+    let mut value = history.quiet_score(color.to_index(), piece, mv.get_dest(), continuations);
+    if controled & (1 << mv.get_dest().to_index()) != 0 && piece != Piece::Pawn {
+        value -= 1000000;
+    }
+    return value
+        + (get_spst_value(color, piece, mv.get_dest())
+            - get_spst_value(color, piece, mv.get_source())) as i32;
+}
+//not a capture, en passant or promotion
+pub fn is_quiet(board: &Board, mv: ChessMove) -> bool {
+    return board.piece_on(mv.get_dest()).is_none()
+        && mv.get_promotion().is_none()
+        && !(board.piece_on(mv.get_source()) == Some(Piece::Pawn)
+            && mv.get_source().get_file() != mv.get_dest().get_file());
+}
+//removes the highest valued move, the list is never sorted as a cutoff usually comes early
+fn pick_best(moves: &mut Vec<(ChessMove, i32)>) -> Option<ChessMove> {
+    if moves.is_empty() {
+        return None;
+    }
+    let mut best = 0;
+    for i in 1..moves.len() {
+        if moves[i].1 > moves[best].1 {
+            best = i;
+        }
+    }
+    return Some(moves.swap_remove(best).0);
+}
+#[derive(PartialEq, Clone, Copy)]
+enum Stage {
+    HashMove,
+    GenerateCaptures,
+    GoodCaptures,
+    Killers,
+    CounterMove,
+    GenerateQuiets,
+    Quiets,
+    BadCaptures,
+    Done,
+}
+//hands out the legal moves of a position best first, one stage at a time. nothing is generated
+//before the hash move is tried and quiets aren't generated or scored until the good captures,
+//killers and counter move are done
+pub struct MovePicker {
+    stage: Stage,
+    iterable: Option<MoveGen>,
     tt_move: ChessMove,
-    killer_moves: &Killers,
-    history: &[[i32; 64]; 6],
+    killers: Killers,
     counter_move: ChessMove,
-) -> Vec<(ChessMove, i32, MoveType)> {
-    let pawns = board.pieces(Piece::Pawn);
-    let controled = if board.side_to_move() == Color::White {
-        ((pawns & board.color_combined(Color::Black)).0 >> 9 & NOT_FILE_H_BB)
-            | ((pawns & board.color_combined(Color::Black)).0 >> 7 & NOT_FILE_A_BB)
-    } else {
-        ((pawns & board.color_combined(Color::White)).0 << 7 & NOT_FILE_H_BB)
-            | ((pawns & board.color_combined(Color::White)).0 << 9 & NOT_FILE_A_BB)
-    };
-    let mut vector = Vec::<(ChessMove, i32, MoveType)>::with_capacity(iterable.len());
-    for mv in iterable {
-        let p = board.piece_on(mv.get_source()).unwrap();
-        vector.push(move_value(
-            &mv,
-            p,
-            board.piece_on(mv.get_dest()),
-            (controled & (1 << mv.get_dest().to_index())) != 0,
-            board.side_to_move(),
-            mv == tt_move,
-            killer_moves.contains(&mv),
-            mv == counter_move,
-            history[p.to_index()][mv.get_dest().to_index()],
-        ));
+    continuations: [Continuation; 2],
+    captures: Vec<(ChessMove, i32)>,
+    bad_captures: Vec<ChessMove>,
+    quiets: Vec<(ChessMove, i32)>,
+    index: usize,
+}
+impl MovePicker {
+    //tt_move has to be legal or the null move, killers and the counter move are checked here
+    pub fn new(
+        tt_move: ChessMove,
+        killers: &Killers,
+        counter_move: ChessMove,
+        continuations: [Continuation; 2],
+    ) -> MovePicker {
+        return MovePicker {
+            stage: Stage::HashMove,
+            iterable: None,
+            tt_move,
+            killers: *killers,
+            counter_move,
+            continuations,
+            captures: Vec::new(),
+            bad_captures: Vec::new(),
+            quiets: Vec::new(),
+            index: 0,
+        };
     }
-    vector.sort_by(|b, a| {
-        if a.2 == b.2 {
-            a.1.cmp(&b.1)
-        } else {
-            a.2.cmp(&b.2)
+    fn is_refutation(&self, mv: ChessMove) -> bool {
+        return mv == self.tt_move || mv == self.counter_move || self.killers.contains(&mv);
+    }
+    pub fn next(&mut self, board: &Board, history: &History) -> Option<(ChessMove, MoveType)> {
+        loop {
+            match self.stage {
+                Stage::HashMove => {
+                    self.stage = Stage::GenerateCaptures;
+                    if self.tt_move != ChessMove::default() {
+                        return Some((self.tt_move, MoveType::HashMove));
+                    }
+                }
+                Stage::GenerateCaptures => {
+                    let mut iterable = MoveGen::new_legal(board);
+                    //the last ranks are included for promotions, other moves there are quiets
+                    let mut targets = board.color_combined(!board.side_to_move()).0 | LAST_RANKS;
+                    if let Some(sq) = board.en_passant() {
+                        targets |= 1 << sq.uforward(board.side_to_move()).to_index();
+                    }
+                    iterable.set_iterator_mask(BitBoard(targets));
+                    for mv in &mut iterable {
+                        if mv == self.tt_move {
+                            continue;
+                        }
+                        if is_quiet(board, mv) {
+                            self.quiets.push((mv, 0));
+                            continue;
+                        }
+                        let under_promotion = mv.get_promotion().is_some()
+                            && mv.get_promotion() != Some(Piece::Queen);
+                        if under_promotion || see(board, mv) < 0 {
+                            self.bad_captures.push(mv);
+                            continue;
+                        }
+                        let piece = board.piece_on(mv.get_source()).unwrap();
+                        //en passant is the only capture with nothing on the destination
+                        let captured = if mv.get_promotion().is_none() {
+                            Some(board.piece_on(mv.get_dest()).unwrap_or(Piece::Pawn))
+                        } else {
+                            board.piece_on(mv.get_dest())
+                        };
+                        //most valuable victim first, the capture history orders similar captures
+                        let mut value =
+                            capture_value(piece, captured, mv.get_promotion()) as i32 * 1024;
+                        if let Some(captured) = captured {
+                            value += history.capture_score(piece, mv.get_dest(), captured);
+                        }
+                        self.captures.push((mv, value));
+                    }
+                    self.iterable = Some(iterable);
+                    self.stage = Stage::GoodCaptures;
+                }
+                Stage::GoodCaptures => {
+                    match pick_best(&mut self.captures) {
+                        Some(mv) if mv.get_promotion().is_some() => {
+                            return Some((mv, MoveType::Promotion))
+                        }
+                        Some(mv) => return Some((mv, MoveType::GoodCapture)),
+                        None => self.stage = Stage::Killers,
+                    };
+                }
+                Stage::Killers => {
+                    while self.index < self.killers.len() {
+                        let mv = self.killers[self.index];
+                        self.index += 1;
+                        //killers come from other positions at the same ply
+                        if mv != self.tt_move
+                            && mv != ChessMove::default()
+                            && !self.killers[..self.index - 1].contains(&mv)
+                            && is_quiet(board, mv)
+                            && is_legal_move(board, mv)
+                        {
+                            return Some((mv, MoveType::KillerMove));
+                        }
+                    }
+                    self.stage = Stage::CounterMove;
+                }
+                Stage::CounterMove => {
+                    self.stage = Stage::GenerateQuiets;
+                    let mv = self.counter_move;
+                    if mv != self.tt_move
+                        && mv != ChessMove::default()
+                        && !self.killers.contains(&mv)
+                        && is_quiet(board, mv)
+                        && is_legal_move(board, mv)
+                    {
+                        return Some((mv, MoveType::CounterMove));
+                    }
+                }
+                Stage::GenerateQuiets => {
+                    let iterable = self.iterable.as_mut().unwrap();
+                    iterable.set_iterator_mask(!EMPTY);
+                    for mv in iterable {
+                        self.quiets.push((mv, 0));
+                    }
+                    //the hash move, killers and counter move were already tried
+                    let mut quiets = std::mem::take(&mut self.quiets);
+                    quiets.retain(|q| !self.is_refutation(q.0));
+                    let them = !board.side_to_move();
+                    let controled = pawn_attacks(
+                        them,
+                        (board.pieces(Piece::Pawn) & board.color_combined(them)).0,
+                    );
+                    for q in quiets.iter_mut() {
+                        q.1 = quiet_value(board, q.0, controled, history, &self.continuations);
+                    }
+                    self.quiets = quiets;
+                    self.stage = Stage::Quiets;
+                }
+                Stage::Quiets => match pick_best(&mut self.quiets) {
+                    Some(mv) => return Some((mv, MoveType::QuietMove)),
+                    None => {
+                        self.index = 0;
+                        self.stage = Stage::BadCaptures;
+                    }
+                },
+                Stage::BadCaptures => {
+                    if self.index < self.bad_captures.len() {
+                        self.index += 1;
+                        return Some((self.bad_captures[self.index - 1], MoveType::BadCapture));
+                    }
+                    self.stage = Stage::Done;
+                }
+                Stage::Done => return None,
+            }
         }
-    });
-    return vector;
+    }
 }
-fn capture_value(piece: Piece, captured: Piece, promo: Option<Piece>, is_controled: bool) -> i16 {
-    let mut value = MVV_LVA[captured.to_index()][piece.to_index()] as i16;
-    if is_controled && piece != Piece::Pawn && piece_value(piece) > piece_value(captured) {
-        value -= 50;
-    } else if promo.is_some() {
-        value += promotion_value(promo.unwrap()) as i16;
+fn capture_value(piece: Piece, captured: Option<Piece>, promo: Option<Piece>) -> i16 {
+    let mut value = match captured {
+        Some(captured) => MVV_LVA[captured.to_index()][piece.to_index()] as i16,
+        None => 0,
+    };
+    if let Some(promo) = promo {
+        value += promotion_value(promo) as i16;
     }
     return value;
 }
+//captures that don't lose material, most valuable victim first
 pub fn sort_captures(iterable: &mut MoveGen, board: &Board) -> Vec<ChessMove> {
-    let pawns = board.pieces(Piece::Pawn);
-    let controled = if board.side_to_move() == Color::White {
-        ((pawns & board.color_combined(Color::Black)).0 >> 9 & NOT_FILE_H_BB)
-            | ((pawns & board.color_combined(Color::Black)).0 >> 7 & NOT_FILE_A_BB)
-    } else {
-        ((pawns & board.color_combined(Color::White)).0 << 7 & NOT_FILE_H_BB)
-            | ((pawns & board.color_combined(Color::White)).0 << 9 & NOT_FILE_A_BB)
-    };
     let mut vector = Vec::<(ChessMove, i16)>::with_capacity(iterable.len());
     for mv in iterable {
+        if see(board, mv) < 0 {
+            continue;
+        }
         vector.push((
             mv,
             capture_value(
                 board.piece_on(mv.get_source()).unwrap(),
-                board.piece_on(mv.get_dest()).unwrap(),
+                board.piece_on(mv.get_dest()),
                 mv.get_promotion(),
-                (controled & (1 << mv.get_dest().to_index())) != 0,
             ),
         ));
     }
     vector.sort_by(|b, a| a.1.cmp(&b.1));
     return vector.iter().map(|t| t.0).collect();
 }
+//quiet moves that give check and don't lose material, searched at the first quiescence ply
+pub fn quiet_checks(board: &Board) -> Vec<ChessMove> {
+    let them = !board.side_to_move();
+    let king = board.king_square(them).to_index();
+    let occupied = board.combined().0;
+    let bishop_checks = get_bishop_moves(king, occupied);
+    let rook_checks = get_rook_moves(king, occupied);
+    //our pieces next in line from the enemy king can give a discovered check by moving away
+    let blockers = (bishop_checks | rook_checks) & board.color_combined(!them).0;
+    let mut iterable = MoveGen::new_legal(board);
+    iterable.set_iterator_mask(!board.combined());
+    let mut checks = Vec::new();
+    for mv in iterable {
+        let piece = mv
+            .get_promotion()
+            .unwrap_or(board.piece_on(mv.get_source()).unwrap());
+        let checking_squares = match piece {
+            Piece::Pawn => pawn_attacks(them, 1 << king),
+            Piece::Knight => get_knight_moves(king),
+            Piece::Bishop => bishop_checks,
+            Piece::Rook => rook_checks,
+            Piece::Queen => bishop_checks | rook_checks,
+            Piece::King => 0,
+        };
+        if checking_squares & (1 << mv.get_dest().to_index()) == 0
+            && blockers & (1 << mv.get_source().to_index()) == 0
+        {
+            continue;
+        }
+        if see(board, mv) >= 0 && board.make_move_new(mv).checkers().0 != 0 {
+            checks.push(mv);
+        }
+    }
+    return checks;
+}
+fn pawn_attacks(color: Color, pawns: u64) -> u64 {
+    if color == Color::White {
+        return (pawns << 7 & NOT_FILE_H_BB) | (pawns << 9 & NOT_FILE_A_BB);
+    }
+    return (pawns >> 9 & NOT_FILE_H_BB) | (pawns >> 7 & NOT_FILE_A_BB);
+}
+//pieces of both colors attacking sq, sliders see through the squares missing from occupied
+fn attackers_to(board: &Board, square: Square, occupied: u64) -> u64 {
+    let sq = square.to_index();
+    let sq_bb = 1u64 << sq;
+    let pawns = board.pieces(Piece::Pawn).0;
+    let queens = board.pieces(Piece::Queen).0;
+    let diagonal = board.pieces(Piece::Bishop).0 | queens;
+    let orthogonal = board.pieces(Piece::Rook).0 | queens;
+    return ((pawn_attacks(Color::Black, sq_bb) & pawns & board.color_combined(Color::White).0)
+        | (pawn_attacks(Color::White, sq_bb) & pawns & board.color_combined(Color::Black).0)
+        | (get_knight_moves(sq) & board.pieces(Piece::Knight).0)
+        //data::KING_ATTACKS_BITBOARD is the wider king zone used by the evaluation
+        | (get_king_moves(square).0 & board.pieces(Piece::King).0)
+        | (get_bishop_moves(sq, occupied) & diagonal)
+        | (get_rook_moves(sq, occupied) & orthogonal))
+        & occupied;
+}
+//static exchange evaluation: material won by the side to move (in centipawns) if both sides
+//keep recapturing on the destination square with their least valuable attacker
+pub fn see(board: &Board, mv: ChessMove) -> i16 {
+    let from = mv.get_source().to_index();
+    let to = mv.get_dest().to_index();
+    let mut occupied = board.combined().0 ^ (1 << from);
+    let mut on_square = board.piece_on(mv.get_source()).unwrap();
+    let mut gain = [0i16; 32];
+    gain[0] = match board.piece_on(mv.get_dest()) {
+        Some(p) => SEE_VALUES[p.to_index()],
+        None if on_square == Piece::Pawn && (from % 8) != (to % 8) => {
+            //en passant, the captured pawn is behind the destination square
+            occupied ^= 1
+                << (if board.side_to_move() == Color::White {
+                    to - 8
+                } else {
+                    to + 8
+                });
+            SEE_VALUES[Piece::Pawn.to_index()]
+        }
+        None => 0,
+    };
+    if let Some(promo) = mv.get_promotion() {
+        gain[0] += SEE_VALUES[promo.to_index()] - SEE_VALUES[Piece::Pawn.to_index()];
+        on_square = promo;
+    }
+    let diagonal = board.pieces(Piece::Bishop).0 | board.pieces(Piece::Queen).0;
+    let orthogonal = board.pieces(Piece::Rook).0 | board.pieces(Piece::Queen).0;
+    let mut attackers = attackers_to(board, mv.get_dest(), occupied);
+    let mut side = !board.side_to_move();
+    let mut d = 0;
+    loop {
+        let side_attackers = attackers & board.color_combined(side).0;
+        if side_attackers == 0 || d + 1 == gain.len() {
+            break;
+        }
+        let piece = ALL_PIECES
+            .iter()
+            .find(|p| side_attackers & board.pieces(**p).0 != 0)
+            .copied()
+            .unwrap();
+        //the king can only recapture if nothing defends the square anymore
+        if piece == Piece::King && attackers & board.color_combined(!side).0 != 0 {
+            break;
+        }
+        d += 1;
+        gain[d] = SEE_VALUES[on_square.to_index()] - gain[d - 1];
+        let attacker = side_attackers & board.pieces(piece).0;
+        occupied ^= attacker & attacker.wrapping_neg();
+        //removing the attacker may uncover a slider behind it
+        attackers |= (get_bishop_moves(to, occupied) & diagonal)
+            | (get_rook_moves(to, occupied) & orthogonal);
+        attackers &= occupied;
+        on_square = piece;
+        side = !side;
+    }
+    while d > 0 {
+        gain[d - 1] = -(-gain[d - 1]).max(gain[d]);
+        d -= 1;
+    }
+    return gain[0];
+}
+//checks a move that didn't come from the move generator, like a hash move, without generating
+//every legal move. castling and en passant are rare enough to leave to board.legal
+pub fn is_legal_move(board: &Board, mv: ChessMove) -> bool {
+    let from = mv.get_source();
+    let to = mv.get_dest();
+    let us = board.side_to_move();
+    let own = board.color_combined(us).0;
+    let occupied = board.combined().0;
+    let to_bb = 1u64 << to.to_index();
+    let piece = match board.piece_on(from) {
+        Some(p) if own & (1 << from.to_index()) != 0 => p,
+        _ => return false,
+    };
+    if own & to_bb != 0 {
+        return false;
+    }
+    let last_rank = if us == Color::White { 7 } else { 0 };
+    let promotes = piece == Piece::Pawn && to.get_rank().to_index() == last_rank;
+    match mv.get_promotion() {
+        Some(Piece::Pawn) | Some(Piece::King) => return false,
+        Some(_) if !promotes => return false,
+        None if promotes => return false,
+        _ => {}
+    }
+    let targets = match piece {
+        Piece::King => {
+            if from
+                .get_file()
+                .to_index()
+                .abs_diff(to.get_file().to_index())
+                == 2
+            {
+                return board.legal(mv);
+            }
+            return get_king_moves(from).0 & to_bb != 0 && MoveGen::legal_quick(board, mv);
+        }
+        Piece::Pawn => {
+            if from.get_file() != to.get_file() && occupied & to_bb == 0 {
+                return board.legal(mv);
+            }
+            let from_bb = 1u64 << from.to_index();
+            let (single, double) = if us == Color::White {
+                let single = from_bb << 8 & !occupied;
+                (single, (single & 0xff0000) << 8 & !occupied)
+            } else {
+                let single = from_bb >> 8 & !occupied;
+                (single, (single & 0xff0000000000) >> 8 & !occupied)
+            };
+            single | double | (pawn_attacks(us, from_bb) & board.color_combined(!us).0)
+        }
+        Piece::Knight => get_knight_moves(from.to_index()),
+        Piece::Bishop => get_bishop_moves(from.to_index(), occupied),
+        Piece::Rook => get_rook_moves(from.to_index(), occupied),
+        Piece::Queen => {
+            get_bishop_moves(from.to_index(), occupied) | get_rook_moves(from.to_index(), occupied)
+        }
+    };
+    if targets & to_bb == 0 {
+        return false;
+    }
+    let king = board.king_square(us);
+    let checkers = board.checkers().0;
+    if checkers != 0 {
+        //with two checkers only the king can move
+        if checkers & (checkers - 1) != 0 {
+            return false;
+        }
+        let checker = board.checkers().to_square();
+        if to_bb & (checkers | between(king, checker).0) == 0 {
+            return false;
+        }
+    }
+    //a pinned piece may only move along the pin
+    if board.pinned().0 & (1 << from.to_index()) != 0 && line(king, from).0 & to_bb == 0 {
+        return false;
+    }
+    return true;
+}
 pub fn get_rook_moves(sq: usize, blockers: u64) -> u64 {
     let magic = MAGIC_NUMBERS[ROOK][sq];
     return MOVES[(magic.offset as usize)
-        + ((magic.magic_number * (blockers & magic.mask)) >> magic.rightshift) as usize]
+        + (magic.magic_number.wrapping_mul(blockers & magic.mask) >> magic.rightshift) as usize]
         & RAYS[ROOK][sq];
 }
 pub fn get_bishop_moves(sq: usize, blockers: u64) -> u64 {
     let magic = MAGIC_NUMBERS[BISHOP][sq];
     return MOVES[(magic.offset as usize)
-        + ((magic.magic_number * (blockers & magic.mask)) >> magic.rightshift) as usize]
+        + (magic.magic_number.wrapping_mul(blockers & magic.mask) >> magic.rightshift) as usize]
         & RAYS[BISHOP][sq];
 }
 pub fn get_knight_moves(sq: usize) -> u64 {
     return KNIGHT_MOVES[sq];
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::init;
+    use std::str::FromStr;
+    fn see_of(fen: &str, mv: &str) -> i16 {
+        init();
+        return see(
+            &Board::from_str(fen).unwrap(),
+            ChessMove::from_str(mv).unwrap(),
+        );
+    }
+    #[test]
+    fn see_of_undefended_capture_is_the_captured_piece() {
+        assert_eq!(see_of("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1", "e4d5"), 100);
+        assert_eq!(
+            see_of("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1", "e1e5"),
+            100
+        );
+    }
+    #[test]
+    fn see_of_defended_captures() {
+        //pawn for pawn
+        assert_eq!(see_of("4k3/8/4p3/3p4/4P3/8/8/4K3 w - - 0 1", "e4d5"), 0);
+        //queen for pawn
+        assert_eq!(see_of("4k3/8/4p3/3p4/8/8/8/3QK3 w - - 0 1", "d1d5"), -800);
+        //knight for pawn, with sliders lined up behind the attackers on both sides
+        assert_eq!(
+            see_of(
+                "1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1",
+                "d3e5"
+            ),
+            -200
+        );
+    }
+    #[test]
+    fn see_of_quiet_moves_and_special_captures() {
+        //a quiet move onto a square the pawn guards
+        assert_eq!(see_of("4k3/8/4p3/8/8/8/8/3QK3 w - - 0 1", "d1d5"), -900);
+        //en passant wins the pawn behind the destination square
+        assert_eq!(see_of("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), 100);
+        //promoting gains a queen for the pawn
+        assert_eq!(see_of("4k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a7a8q"), 800);
+    }
+    //every move the picker yields, in order
+    fn picked_moves(
+        board: &Board,
+        tt_move: ChessMove,
+        killers: &Killers,
+        counter: ChessMove,
+    ) -> Vec<ChessMove> {
+        let history = History::new();
+        let mut picker = MovePicker::new(tt_move, killers, counter, [None, None]);
+        let mut moves = Vec::new();
+        while let Some((mv, _)) = picker.next(board, &history) {
+            moves.push(mv);
+        }
+        return moves;
+    }
+    const PICKER_POSITIONS: [&str; 6] = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        //en passant and promotions with and without captures
+        "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+        "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1",
+        //in check, only evasions are legal
+        "r3k2r/p1pp1pb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBqPPP/R3K2R w KQkq - 0 1",
+        "rnbqkbnr/ppp2ppp/8/1B1pp3/4P3/8/PPPP1PPP/RNBQK1NR b KQkq - 1 3",
+    ];
+    #[test]
+    fn picker_yields_every_legal_move_once() {
+        init();
+        for fen in PICKER_POSITIONS {
+            let board = Board::from_str(fen).unwrap();
+            let legal: Vec<ChessMove> = MoveGen::new_legal(&board).collect();
+            for tt_move in [ChessMove::default()].iter().chain(legal.iter()) {
+                let mut moves = picked_moves(
+                    &board,
+                    *tt_move,
+                    &[ChessMove::default(); 3],
+                    ChessMove::default(),
+                );
+                if *tt_move != ChessMove::default() {
+                    assert_eq!(moves[0], *tt_move, "{}", fen);
+                }
+                moves.sort();
+                let mut expected = legal.clone();
+                expected.sort();
+                assert_eq!(moves, expected, "{} hash move {}", fen, tt_move);
+            }
+        }
+    }
+    #[test]
+    fn picker_yields_duplicate_killers_once() {
+        init();
+        let board = Board::from_str(PICKER_POSITIONS[0]).unwrap();
+        let nc3 = ChessMove::from_str("b1c3").unwrap();
+        let nf3 = ChessMove::from_str("g1f3").unwrap();
+        let mut moves = picked_moves(&board, ChessMove::default(), &[nc3, nf3, nf3], nf3);
+        assert_eq!(moves[..2], [nc3, nf3]);
+        moves.sort();
+        let mut expected: Vec<ChessMove> = MoveGen::new_legal(&board).collect();
+        expected.sort();
+        assert_eq!(moves, expected);
+    }
+    #[test]
+    fn store_killer_keeps_distinct_killers() {
+        let mut history = History::new();
+        let moves = ["b1c3", "g1f3", "e2e4", "d2d4"].map(|m| ChessMove::from_str(m).unwrap());
+        for mv in moves {
+            history.store_killer(0, mv, 0, Piece::Pawn, Square::E4);
+        }
+        assert_eq!(*history.get_killers(0), [moves[3], moves[2], moves[1]]);
+    }
+    #[test]
+    fn en_passant_and_promotions_are_not_quiet() {
+        init();
+        let board = Board::from_str("4k3/P7/8/3pP3/8/8/8/4K3 w - d6 0 1").unwrap();
+        let quiet = |m: &str| is_quiet(&board, ChessMove::from_str(m).unwrap());
+        assert!(quiet("e5e6"));
+        assert!(quiet("e1d2"));
+        assert!(!quiet("e5d6"));
+        assert!(!quiet("a7a8q"));
+        assert!(!quiet("a7a8n"));
+    }
+    #[test]
+    fn quiet_checks_are_the_safe_checking_non_captures() {
+        init();
+        //discovered checks, a knight fork square and checking promotions
+        let fens = [
+            "4k3/8/8/8/8/4N3/8/4RK2 w - - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "3k4/1P6/8/8/8/8/8/B3K2R w K - 0 1",
+        ];
+        for fen in PICKER_POSITIONS.iter().chain(fens.iter()) {
+            let board = Board::from_str(fen).unwrap();
+            let mut checks = quiet_checks(&board);
+            checks.sort();
+            let mut expected: Vec<ChessMove> = MoveGen::new_legal(&board)
+                .filter(|mv| board.piece_on(mv.get_dest()).is_none())
+                .filter(|mv| board.make_move_new(*mv).checkers().0 != 0 && see(&board, *mv) >= 0)
+                .collect();
+            expected.sort();
+            assert_eq!(checks, expected, "{}", fen);
+        }
+    }
+    #[test]
+    fn picker_skips_illegal_killers_and_counter_moves() {
+        init();
+        //moves that are legal in one of the positions but not in the others
+        let foreign: Vec<ChessMove> = PICKER_POSITIONS
+            .iter()
+            .flat_map(|fen| MoveGen::new_legal(&Board::from_str(fen).unwrap()))
+            .collect();
+        for fen in PICKER_POSITIONS {
+            let board = Board::from_str(fen).unwrap();
+            let mut expected: Vec<ChessMove> = MoveGen::new_legal(&board).collect();
+            expected.sort();
+            for chunk in foreign.chunks(4) {
+                let killers = [chunk[0], chunk[chunk.len() / 2], chunk[chunk.len() - 1]];
+                let mut moves = picked_moves(
+                    &board,
+                    ChessMove::default(),
+                    &killers,
+                    chunk[1 % chunk.len()],
+                );
+                moves.sort();
+                assert_eq!(moves, expected, "{} killers {:?}", fen, killers);
+            }
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use crate::transposition_table::{
+    DEFAULT_HASH_MB, DEFAULT_PAWN_HASH_MB, MAX_HASH_MB, MAX_PAWN_HASH_MB,
+};
+pub const DEFAULT_BOOK_FILE: &str = "<empty>";
+const MAX_THREADS: i32 = 256;
+pub enum OptionKind {
+    Check { default: bool },
+    Spin { default: i32, min: i32, max: i32 },
+    String { default: &'static str },
+    Button,
+}
+pub struct EngineOption {
+    pub name: &'static str,
+    pub kind: OptionKind,
+}
+pub enum OptionValue {
+    Check(bool),
+    Spin(i32),
+    String(String),
+    Button,
+}
+//every option the engine understands, in the order they are declared to the gui
+pub const OPTIONS: [EngineOption; 12] = [
+    EngineOption {
+        name: "Hash",
+        kind: OptionKind::Spin {
+            default: DEFAULT_HASH_MB as i32,
+            min: 1,
+            max: MAX_HASH_MB as i32,
+        },
+    },
+    EngineOption {
+        name: "PawnHash",
+        kind: OptionKind::Spin {
+            default: DEFAULT_PAWN_HASH_MB as i32,
+            min: 1,
+            max: MAX_PAWN_HASH_MB as i32,
+        },
+    },
+    EngineOption {
+        name: "Clear Hash",
+        kind: OptionKind::Button,
+    },
+    EngineOption {
+        name: "Threads",
+        kind: OptionKind::Spin {
+            default: 1,
+            min: 1,
+            max: MAX_THREADS,
+        },
+    },
+    EngineOption {
+        name: "MultiPV",
+        kind: OptionKind::Spin {
+            default: 1,
+            min: 1,
+            max: 64,
+        },
+    },
+    EngineOption {
+        name: "Move Overhead",
+        kind: OptionKind::Spin {
+            default: 10,
+            min: 0,
+            max: 5000,
+        },
+    },
+    EngineOption {
+        name: "Contempt",
+        kind: OptionKind::Spin {
+            default: 0,
+            min: -100,
+            max: 100,
+        },
+    },
+    EngineOption {
+        name: "QuiescenceChecks",
+        kind: OptionKind::Check { default: false },
+    },
+    EngineOption {
+        name: "OwnBook",
+        kind: OptionKind::Check { default: true },
+    },
+    EngineOption {
+        name: "BookFile",
+        kind: OptionKind::String {
+            default: DEFAULT_BOOK_FILE,
+        },
+    },
+    EngineOption {
+        name: "Ponder",
+        kind: OptionKind::Check { default: false },
+    },
+    EngineOption {
+        name: "Log",
+        kind: OptionKind::Check { default: false },
+    },
+];
+impl EngineOption {
+    pub fn declaration(&self) -> String {
+        return match self.kind {
+            OptionKind::Check { default } => {
+                format!("option name {} type check default {}", self.name, default)
+            }
+            OptionKind::Spin { default, min, max } => format!(
+                "option name {} type spin default {} min {} max {}",
+                self.name, default, min, max
+            ),
+            OptionKind::String { default } => {
+                format!("option name {} type string default {}", self.name, default)
+            }
+            OptionKind::Button => format!("option name {} type button", self.name),
+        };
+    }
+    pub fn parse(&self, value: Option<&str>) -> Result<OptionValue, String> {
+        if let OptionKind::Button = self.kind {
+            return Ok(OptionValue::Button);
+        }
+        let value = match value {
+            Some(v) => v,
+            None => return Err(format!("missing value for option {}", self.name)),
+        };
+        return match self.kind {
+            OptionKind::Check { .. } => match value {
+                "true" => Ok(OptionValue::Check(true)),
+                "false" => Ok(OptionValue::Check(false)),
+                _ => Err(format!(
+                    "invalid value {} for option {}, expected true or false",
+                    value, self.name
+                )),
+            },
+            OptionKind::Spin { min, max, .. } => match value.parse::<i32>() {
+                Ok(v) if v >= min && v <= max => Ok(OptionValue::Spin(v)),
+                _ => Err(format!(
+                    "invalid value {} for option {}, expected an integer from {} to {}",
+                    value, self.name, min, max
+                )),
+            },
+            OptionKind::String { .. } => Ok(OptionValue::String(value.to_string())),
+            OptionKind::Button => Ok(OptionValue::Button),
+        };
+    }
+}
+//parses "setoption name <id> [value <x>]", option names are case insensitive
+pub fn parse_setoption(cmd: &str) -> Result<(&'static str, OptionValue), String> {
+    let args: Vec<&str> = cmd.split_whitespace().collect();
+    let name_index = match args.iter().position(|r| *r == "name") {
+        Some(i) => i + 1,
+        None => return Err(String::from("setoption without name")),
+    };
+    let value_index = args.iter().position(|r| *r == "value");
+    let name = args[name_index..value_index.unwrap_or(args.len())].join(" ");
+    let value = value_index.map(|i| args[(i + 1)..].join(" "));
+    let option = match OPTIONS.iter().find(|o| o.name.eq_ignore_ascii_case(&name)) {
+        Some(o) => o,
+        None => return Err(format!("unknown option {}", name)),
+    };
+    let value = option.parse(value.as_deref().filter(|v| !v.is_empty()))?;
+    return Ok((option.name, value));
+}
@@ -0,0 +1,99 @@
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+//phase of a position with all the minor and major pieces on the board
+pub const MAX_PHASE: i32 = 24;
+//a middlegame and an endgame value packed in one integer, the endgame value in the upper 16
+//bits, so that adding and subtracting scores works on both halves at once
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct Score(i32);
+impl Score {
+    pub const ZERO: Score = Score(0);
+    pub const fn new(mg: i16, eg: i16) -> Score {
+        return Score(((eg as i32) << 16) + mg as i32);
+    }
+    pub fn mg(self) -> i16 {
+        return self.0 as i16;
+    }
+    pub fn eg(self) -> i16 {
+        //rounds up to undo the borrow a negative middlegame value took from the upper half
+        return ((self.0 + 0x8000) >> 16) as i16;
+    }
+    //blends the two values by the phase, MAX_PHASE is a pure middlegame and 0 a pure endgame
+    pub fn taper(self, phase: i32) -> i16 {
+        let phase = phase.clamp(0, MAX_PHASE);
+        return ((self.mg() as i32 * phase + self.eg() as i32 * (MAX_PHASE - phase)) / MAX_PHASE)
+            as i16;
+    }
+}
+impl Add for Score {
+    type Output = Score;
+    fn add(self, other: Score) -> Score {
+        return Score(self.0 + other.0);
+    }
+}
+impl Sub for Score {
+    type Output = Score;
+    fn sub(self, other: Score) -> Score {
+        return Score(self.0 - other.0);
+    }
+}
+impl Neg for Score {
+    type Output = Score;
+    fn neg(self) -> Score {
+        return Score(-self.0);
+    }
+}
+impl Mul<i32> for Score {
+    type Output = Score;
+    fn mul(self, n: i32) -> Score {
+        return Score(self.0 * n);
+    }
+}
+impl AddAssign for Score {
+    fn add_assign(&mut self, other: Score) {
+        self.0 += other.0;
+    }
+}
+impl SubAssign for Score {
+    fn sub_assign(&mut self, other: Score) {
+        self.0 -= other.0;
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    const VALUES: [i16; 9] = [0, 1, -1, 100, -100, 975, -975, i16::MAX / 2, i16::MIN / 2];
+    #[test]
+    fn packed_values_unpack_unchanged() {
+        for mg in VALUES {
+            for eg in VALUES {
+                let score = Score::new(mg, eg);
+                assert_eq!((score.mg(), score.eg()), (mg, eg));
+            }
+        }
+    }
+    #[test]
+    fn arithmetic_works_on_both_halves() {
+        for (a, b) in [(-30, 45), (100, -100), (-1, -1)] {
+            for (c, d) in [(7, -300), (-975, 20), (0, 1)] {
+                let (x, y) = (Score::new(a, b), Score::new(c, d));
+                assert_eq!(x + y, Score::new(a + c, b + d));
+                assert_eq!(x - y, Score::new(a - c, b - d));
+                assert_eq!(-x, Score::new(-a, -b));
+                assert_eq!(x * 3, Score::new(a * 3, b * 3));
+                let mut z = x;
+                z += y;
+                z -= y;
+                assert_eq!(z, x);
+            }
+        }
+    }
+    #[test]
+    fn taper_blends_by_phase() {
+        let score = Score::new(240, -120);
+        assert_eq!(score.taper(MAX_PHASE), 240);
+        assert_eq!(score.taper(0), -120);
+        assert_eq!(score.taper(MAX_PHASE / 2), 60);
+        //promoted pieces can push the phase past the maximum
+        assert_eq!(score.taper(MAX_PHASE + 4), 240);
+    }
+}
@@ -1,388 +1,1354 @@
 use crate::board::Position;
-use crate::evaluate::PAWN_TT_HITS;
+use crate::data::get_lmr_reduction;
+use crate::history::{history_bonus, Continuation, History};
 use crate::moves::MoveType;
+use crate::time_manager::{iteration_limit, TimeManager};
 use crate::{
     evaluate::evaluate,
-    moves::{sort_captures, sort_moves},
-    transposition_table::{EntryType, TranspositionTable},
+    moves::{is_quiet, quiet_checks, see, sort_captures, MovePicker, SEE_VALUES},
+    transposition_table::{EntryType, PawnTable, PositionEntry, TranspositionTable},
 };
 use chess::{ChessMove, MoveGen, Piece, Rank, Square};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::time::{Duration, Instant};
 const SEARCH_EXIT_KEY: i16 = std::i16::MAX;
 const NEG_SEARCH_EXIT_KEY: i16 = -SEARCH_EXIT_KEY;
 const ALPHA: i16 = -i16::MAX;
 const BETA: i16 = i16::MAX;
-static mut TIME_LIMIT: Duration = Duration::new(0, 0);
-static mut NODES: u32 = 0;
-static mut TT_HITS: u32 = 0;
-static mut BETA_CUTOFFS: u32 = 0;
+pub const MAX_DEPTH: u8 = 50;
+pub const MATE: i16 = 10000;
+//any score above this is a forced mate
+const MATE_BOUND: i16 = MATE - 1000;
+pub const SEARCH_STACK_SIZE: usize = 64 * 1024 * 1024;
+//how often (in nodes) a thread publishes its node count to the others
+const NODE_PUBLISH_INTERVAL: u64 = 1024;
+//forward pruning margins in centipawns, indexed by remaining depth
+const REVERSE_FUTILITY_MARGIN: [i16; 7] = [0, 90, 180, 270, 360, 450, 540];
+const RAZOR_MARGIN: [i16; 3] = [0, 250, 450];
+//quiet moves tried before the rest are pruned, indexed by remaining depth
+const LATE_MOVE_COUNT: [usize; 5] = [0, 6, 9, 14, 21];
+//late move reductions start at this depth and move index
+const LMR_MIN_DEPTH: u8 = 3;
+const LMR_MIN_MOVES: usize = 3;
+//half width of the first aspiration window, doubled on every fail, past the max the window
+//is opened completely
+const ASPIRATION_WINDOW: i32 = 25;
+const MAX_ASPIRATION_WINDOW: i32 = 800;
+//shallower iterations are too unstable to gain anything from aspiration windows
+const ASPIRATION_MIN_DEPTH: u8 = 4;
+//null move pruning is tried from this depth on
+const NULL_MOVE_MIN_DEPTH: u8 = 3;
+//above this depth the null move reduction grows from 2 to 3
+const NULL_MOVE_DEEP_DEPTH: u8 = 6;
+//null move cutoffs at this depth or more are verified by a reduced normal search
+const NULL_MOVE_VERIFICATION_DEPTH: u8 = 8;
+//the hash move of nodes this deep may be extended if it is singular, the hash entry has to
+//be at most this many plies shallower than the node
+const SINGULAR_MIN_DEPTH: u8 = 7;
+const SINGULAR_TT_DEPTH_MARGIN: u8 = 3;
+//the other moves must fail low against the hash score minus this much per ply of depth
+const SINGULAR_MARGIN: i16 = 2;
+//each this much history moves a quiet's late move reduction by a ply
+const LMR_HISTORY_DIVISOR: i32 = 8192;
+//deeper than any line can get, plies are counted in a u8
+const MOVE_STACK_SIZE: usize = 256;
+//captures in the quiescence search must be able to come this close to alpha
+const DELTA_MARGIN: i16 = 200;
+//shared with the uci thread, lets it stop a running search or change its time limits
+#[derive(Default)]
+pub struct SearchControl {
+    pub stop: AtomicBool,
+    //set while pondering or in go infinite, the time limits are ignored until it is cleared
+    pub infinite: AtomicBool,
+    //time limits in milliseconds, atomic so that ponderhit can change them mid search
+    soft_time_limit: AtomicU64,
+    time_limit: AtomicU64,
+}
+impl SearchControl {
+    pub fn set_time_limits(&self, soft: Duration, hard: Duration) {
+        self.soft_time_limit.store(
+            soft.as_millis().min(u64::MAX as u128) as u64,
+            Ordering::Relaxed,
+        );
+        self.time_limit.store(
+            hard.as_millis().min(u64::MAX as u128) as u64,
+            Ordering::Relaxed,
+        );
+    }
+    pub fn is_stopped(&self) -> bool {
+        return self.stop.load(Ordering::Relaxed);
+    }
+    pub fn is_infinite(&self) -> bool {
+        return self.infinite.load(Ordering::Relaxed);
+    }
+}
+#[derive(Default)]
+pub struct SearchStats {
+    pub nodes: u64,
+    pub seldepth: u8,
+    pub tt_hits: u32,
+    pub pawn_tt_hits: u32,
+    pub beta_cutoffs: u32,
+}
+//per thread search data, kept between searches so the move ordering heuristics persist
+pub struct ThreadData {
+    pub pawn_table: PawnTable,
+    pub history: History,
+}
+impl ThreadData {
+    pub fn new(pawn_hash_mb: usize) -> ThreadData {
+        return ThreadData {
+            pawn_table: PawnTable::init(pawn_hash_mb),
+            history: History::new(),
+        };
+    }
+    pub fn clear(&mut self) {
+        //pawn table isn't cleared because of low collision probability
+        self.history.clear();
+    }
+}
+//node count of one thread, padded to a cache line so the threads don't slow each other down
+#[derive(Default)]
+#[repr(align(64))]
+pub struct NodeCounter(AtomicU64);
+#[derive(Clone, Copy)]
+pub struct SearchOptions {
+    pub contempt: i16,
+    pub multi_pv: usize,
+    //quiet checks at the first quiescence ply find more mates but cost more than they gain at
+    //fast time controls, so they are off by default
+    pub quiesce_checks: bool,
+    pub debug: bool,
+}
+pub struct Searcher<'a> {
+    tt: &'a TranspositionTable,
+    td: &'a mut ThreadData,
+    control: &'a SearchControl,
+    node_counters: &'a [NodeCounter],
+    //thread 0 is the main thread, the others are helpers that only fill the hash table
+    id: usize,
+    start: Instant,
+    node_limit: u64,
+    contempt: i16,
+    multi_pv: usize,
+    quiesce_checks: bool,
+    debug: bool,
+    //hashes of the positions since the last irreversible move, the game's followed by the
+    //current search line, the top one is the position being searched
+    hash_stack: Vec<u64>,
+    //index of the root position in hash_stack
+    root_index: usize,
+    //the move made at each ply of the current line, None for a null move
+    move_stack: [Continuation; MOVE_STACK_SIZE],
+    pub stats: SearchStats,
+}
 pub struct SearchResult {
     pub eval: i16,
     pub best_move: ChessMove,
     pub depth: u8,
     pub duration: Duration,
-    pub nodes: u32,
+    pub nodes: u64,
+}
+//everything "go" can restrict the search with, a None time searches until stopped. a depth or
+//node limited search with one thread always plays the same move, with more the threads race
+pub struct SearchLimits {
+    pub depth: u8,
+    pub nodes: Option<u64>,
+    pub mate: Option<u8>,
+    pub time: Option<TimeManager>,
+    pub search_moves: Vec<ChessMove>,
+}
+impl Default for SearchLimits {
+    fn default() -> Self {
+        return SearchLimits {
+            depth: MAX_DEPTH,
+            nodes: None,
+            mate: None,
+            time: None,
+            search_moves: Vec::new(),
+        };
+    }
 }
 
-fn quiesce(board: &Position, alpha: i16, beta: i16, tt: &mut TranspositionTable) -> i16 {
-    let mut alpha = alpha;
-    unsafe {
-        NODES += 1;
-    }
-    let stand_pat = evaluate(board, tt);
-    if stand_pat >= beta {
-        unsafe {
-            BETA_CUTOFFS += 1;
-        }
-        return beta;
-    }
-    if alpha < stand_pat {
-        alpha = stand_pat;
-    }
-    let mut iterable = MoveGen::new_legal(&board.board);
-    let targets = board.color_combined(!board.side_to_move());
-    iterable.set_iterator_mask(*targets);
-    let moves = sort_captures(&mut iterable, &board.board);
-    for mv in moves {
-        let score = -quiesce(&board.make_move_new(mv), -beta, -alpha, tt);
-        if score >= beta {
-            return beta;
+impl<'a> Searcher<'a> {
+    pub fn new(
+        tt: &'a TranspositionTable,
+        td: &'a mut ThreadData,
+        control: &'a SearchControl,
+        node_counters: &'a [NodeCounter],
+        id: usize,
+        options: SearchOptions,
+    ) -> Searcher<'a> {
+        return Searcher {
+            tt,
+            td,
+            control,
+            node_counters,
+            id,
+            start: Instant::now(),
+            node_limit: u64::MAX,
+            contempt: options.contempt,
+            multi_pv: options.multi_pv,
+            quiesce_checks: options.quiesce_checks,
+            debug: options.debug,
+            hash_stack: Vec::new(),
+            root_index: 0,
+            move_stack: [None; MOVE_STACK_SIZE],
+            stats: SearchStats::default(),
+        };
+    }
+    //the moves one and two plies before the node at this ply
+    fn continuations(&self, ply_from_root: u8) -> [Continuation; 2] {
+        let ply = ply_from_root as usize;
+        return [
+            if ply >= 1 {
+                self.move_stack[ply - 1]
+            } else {
+                None
+            },
+            if ply >= 2 {
+                self.move_stack[ply - 2]
+            } else {
+                None
+            },
+        ];
+    }
+    fn is_main(&self) -> bool {
+        return self.id == 0;
+    }
+    fn count_node(&mut self, ply_from_root: u8) {
+        self.stats.nodes += 1;
+        self.stats.seldepth = self.stats.seldepth.max(ply_from_root);
+        if self.stats.nodes.is_multiple_of(NODE_PUBLISH_INTERVAL) {
+            self.node_counters[self.id]
+                .0
+                .store(self.stats.nodes, Ordering::Relaxed);
         }
-        if score > alpha {
-            alpha = score;
+    }
+    //nodes searched by all threads, exact for this thread and slightly behind for the others
+    fn total_nodes(&self) -> u64 {
+        let mut nodes = self.stats.nodes;
+        for (i, counter) in self.node_counters.iter().enumerate() {
+            if i != self.id {
+                nodes += counter.0.load(Ordering::Relaxed);
+            }
         }
+        return nodes;
     }
-    return alpha;
-}
-fn alpha_beta(
-    board: &Position,
-    ply_from_root: u8,
-    depth: u8,
-    extended: u8,
-    alpha: i16,
-    beta: i16,
-    init: &Instant,
-    tt: &mut TranspositionTable,
-    prev_piece: Piece,
-    prev_sq: Square,
-) -> i16 {
-    unsafe {
-        if init.elapsed() >= TIME_LIMIT {
-            return SEARCH_EXIT_KEY;
+    //go nodes counts the nodes of all threads, only the main thread has a limit and it stops
+    //the helpers when it returns
+    fn node_limit_reached(&self) -> bool {
+        return self.node_limit != u64::MAX && self.total_nodes() >= self.node_limit;
+    }
+    //draws are scored from the root side's perspective, a positive contempt avoids them
+    fn draw_score(&self, ply_from_root: u8) -> i16 {
+        if ply_from_root.is_multiple_of(2) {
+            return -self.contempt;
         }
-        NODES += 1;
+        return self.contempt;
     }
-    let key = board.get_hash();
-    let tt_value = tt.look_up_pos(key, &board);
-    let tt_move = if tt_value.is_some() {
-        tt_value.unwrap().best_move
-    } else {
-        ChessMove::default()
-    };
-    if tt_value.is_some() && (depth <= tt_value.unwrap().depth) {
-        unsafe {
-            TT_HITS += 1;
-        }
-        match tt_value.unwrap().entry_type {
-            EntryType::Exact => return tt_value.unwrap().eval,
-            EntryType::UpperBound => {
-                if tt_value.unwrap().eval <= alpha {
-                    return alpha;
+    //a position repeated inside the search tree is a draw, repeating a position from before
+    //the root only counts once it happened twice, as it would be a threefold repetition
+    fn is_repetition(&self, board: &Position) -> bool {
+        let top = self.hash_stack.len() - 1;
+        let hash = self.hash_stack[top];
+        let oldest = top.saturating_sub(board.halfmove_clock() as usize);
+        let mut count = 0;
+        let mut i = top;
+        while i >= oldest + 2 {
+            i -= 2;
+            if self.hash_stack[i] == hash {
+                if i >= self.root_index {
+                    return true;
                 }
-            }
-            EntryType::LowerBound => {
-                if tt_value.unwrap().eval >= beta {
-                    return beta;
+                count += 1;
+                if count == 2 {
+                    return true;
                 }
             }
-            EntryType::None => {}
         }
+        return false;
     }
-    if depth == 0 {
-        return quiesce(board, alpha, beta, tt);
+    fn is_fifty_move_draw(&self, board: &Position) -> bool {
+        //checkmate on the hundredth ply still wins
+        return board.halfmove_clock() >= 100
+            && (board.checkers() == 0 || MoveGen::new_legal(&board.board).len() != 0);
     }
-
-    let mut moves = if tt_move != ChessMove::default() {
-        vec![(tt_move, 0, MoveType::HashMove)]
-    } else {
-        let mut iterable = MoveGen::new_legal(&board.board);
-        sort_moves(
-            &mut iterable,
-            &board.board,
-            tt_move,
-            tt.get_killers(ply_from_root as usize),
-            &tt.history[board.side_to_move().to_index()],
-            tt.counters[board.side_to_move().to_index()][prev_piece.to_index()][prev_sq.to_index()],
-        )
-    };
-    if moves.len() == 0 {
-        if board.checkers() == 0 {
-            return 0;
+    //helpers don't keep time, they run until the main thread stops them
+    fn time_is_up(&self) -> bool {
+        return self.is_main()
+            && !self.control.is_infinite()
+            && self.start.elapsed().as_millis() as u64
+                >= self.control.time_limit.load(Ordering::Relaxed);
+    }
+    //searches captures until the position is quiet. in check every evasion is searched, and
+    //with checks set quiet checking moves are tried as well
+    fn quiesce(
+        &mut self,
+        board: &Position,
+        ply_from_root: u8,
+        alpha: i16,
+        beta: i16,
+        checks: bool,
+    ) -> i16 {
+        self.count_node(ply_from_root);
+        let key = board.get_hash();
+        let tt_value = self.tt.look_up_pos(key, board).map(|mut entry| {
+            entry.eval = score_from_tt(entry.eval, ply_from_root);
+            return entry;
+        });
+        if let Some(entry) = tt_value {
+            match entry.entry_type {
+                EntryType::Exact => return entry.eval.clamp(alpha, beta),
+                EntryType::UpperBound if entry.eval <= alpha => return alpha,
+                EntryType::LowerBound if entry.eval >= beta => return beta,
+                _ => {}
+            }
+        }
+        let in_check = board.checkers() != 0;
+        let static_eval = if in_check {
+            ALPHA
+        } else if tt_value.is_some() && tt_value.unwrap().static_eval != ALPHA {
+            tt_value.unwrap().static_eval
         } else {
-            return -10000 + ply_from_root as i16;
-        }
-    }
-    let mut best_move = moves[0].0;
-    let mut best_move_piece = board.piece_on(moves[0].0.get_source()).unwrap();
-    let mut alpha = alpha;
-    let mut tt_type = EntryType::UpperBound;
-    let mut i = 0;
-    while i < moves.len() {
-        let mv = moves[i].0;
-        let piece = board.piece_on(mv.get_source()).unwrap();
-        let is_capture = board.piece_on(mv.get_dest()).is_some();
-        let new_board = board.make_move_new(mv);
-        tt.bf_history[board.side_to_move().to_index()][piece.to_index()]
-            [mv.get_dest().to_index()] += 1;
-        let is_check = board.checkers() != 0;
-        let mut extention = if is_check && extended < 6 { 1 } else { 0 };
-        let rank = mv.get_dest().get_rank();
-        if piece == Piece::Pawn && (rank == Rank::Second || rank == Rank::Seventh) {
-            extention += 1;
-        }
-        let reduction: u8 = if extention == 0
-            && ply_from_root > 2
-            && !is_capture
-            && i > 4
-            && mv.get_promotion().is_none()
-            && depth > 3
-        {
-            1
+            evaluate(board, &mut self.td.pawn_table, &mut self.stats)
+        };
+        let mut alpha = alpha;
+        let mut tt_type = EntryType::UpperBound;
+        let mut best_move = ChessMove::default();
+        let moves = if in_check {
+            //no standing pat in check, every evasion has to be tried
+            let mut picker = MovePicker::new(
+                tt_value.map_or(ChessMove::default(), |e| e.best_move),
+                &self.td.history.default_killers,
+                ChessMove::default(),
+                self.continuations(ply_from_root),
+            );
+            let mut evasions = Vec::new();
+            while let Some((mv, _)) = picker.next(&board.board, &self.td.history) {
+                evasions.push(mv);
+            }
+            if evasions.is_empty() {
+                return -MATE + ply_from_root as i16;
+            }
+            evasions
         } else {
-            0
+            if static_eval >= beta {
+                self.stats.beta_cutoffs += 1;
+                self.tt.set_pos(
+                    key,
+                    static_eval,
+                    static_eval,
+                    EntryType::LowerBound,
+                    0,
+                    best_move,
+                    false,
+                );
+                return beta;
+            }
+            if alpha < static_eval {
+                alpha = static_eval;
+                tt_type = EntryType::Exact;
+            }
+            let mut iterable = MoveGen::new_legal(&board.board);
+            let targets = board.color_combined(!board.side_to_move());
+            iterable.set_iterator_mask(*targets);
+            let mut moves = sort_captures(&mut iterable, &board.board);
+            if checks {
+                moves.extend(quiet_checks(&board.board));
+            }
+            moves
         };
-        let mut score = 0;
-        let mut needs_full_search = true;
-        if reduction != 0 {
-            score = -alpha_beta(
-                &new_board,
-                ply_from_root + 1,
-                depth - 1 - reduction,
-                extended,
+        for mv in moves {
+            //delta pruning: even winning the captured piece for free won't get close to alpha
+            if !in_check && mv.get_promotion().is_none() {
+                if let Some(captured) = board.piece_on(mv.get_dest()) {
+                    if static_eval + SEE_VALUES[captured.to_index()] + DELTA_MARGIN <= alpha {
+                        continue;
+                    }
+                }
+            }
+            //evasions deeper in quiescence are ordered by the moves leading to them
+            self.move_stack[ply_from_root as usize] =
+                Some((board.piece_on(mv.get_source()).unwrap(), mv.get_dest()));
+            let score = -self.quiesce(
+                &board.make_move_new(mv),
+                ply_from_root.saturating_add(1),
                 -beta,
                 -alpha,
-                init,
-                tt,
-                piece,
-                mv.get_dest(),
+                false,
             );
-            needs_full_search = score > alpha;
+            if score >= beta {
+                self.tt.set_pos(
+                    key,
+                    score_to_tt(score, ply_from_root),
+                    static_eval,
+                    EntryType::LowerBound,
+                    0,
+                    mv,
+                    false,
+                );
+                return beta;
+            }
+            if score > alpha {
+                alpha = score;
+                tt_type = EntryType::Exact;
+                best_move = mv;
+            }
         }
-        if needs_full_search {
-            score = -alpha_beta(
-                &new_board,
-                ply_from_root + 1,
-                depth + extention - 1,
-                extended + extention,
-                -beta,
-                -alpha,
-                init,
-                tt,
-                piece,
-                mv.get_dest(),
-            );
+        self.tt.set_pos(
+            key,
+            score_to_tt(alpha, ply_from_root),
+            static_eval,
+            tt_type,
+            0,
+            best_move,
+            false,
+        );
+        return alpha;
+    }
+    //singular extension: the hash move is extended when every other move fails low against a
+    //bound a little below its stored score, searched at reduced depth
+    #[allow(clippy::too_many_arguments)]
+    fn is_singular(
+        &mut self,
+        board: &Position,
+        ply_from_root: u8,
+        depth: u8,
+        extended: u8,
+        tt_value: Option<PositionEntry>,
+        prev_piece: Piece,
+        prev_sq: Square,
+    ) -> bool {
+        let entry = match tt_value {
+            Some(entry) => entry,
+            None => return false,
+        };
+        if depth < SINGULAR_MIN_DEPTH
+            || entry.depth + SINGULAR_TT_DEPTH_MARGIN < depth
+            || entry.entry_type == EntryType::UpperBound
+            || entry.eval.abs() >= MATE_BOUND
+        {
+            return false;
         }
-        if score == NEG_SEARCH_EXIT_KEY {
+        let singular_beta = entry.eval - SINGULAR_MARGIN * depth as i16;
+        let score = self.alpha_beta(
+            board,
+            ply_from_root,
+            (depth - 1) / 2,
+            extended,
+            singular_beta - 1,
+            singular_beta,
+            prev_piece,
+            prev_sq,
+            false,
+            entry.best_move,
+        );
+        return score != SEARCH_EXIT_KEY && score < singular_beta;
+    }
+    #[allow(clippy::too_many_arguments)]
+    fn alpha_beta(
+        &mut self,
+        board: &Position,
+        ply_from_root: u8,
+        depth: u8,
+        extended: u8,
+        alpha: i16,
+        beta: i16,
+        prev_piece: Piece,
+        prev_sq: Square,
+        null_allowed: bool,
+        excluded: ChessMove,
+    ) -> i16 {
+        if self.control.is_stopped() || self.time_is_up() || self.node_limit_reached() {
             return SEARCH_EXIT_KEY;
         }
-        if score >= beta {
-            unsafe {
-                BETA_CUTOFFS += 1;
+        self.count_node(ply_from_root);
+        if self.is_repetition(board) || self.is_fifty_move_draw(board) {
+            return self.draw_score(ply_from_root);
+        }
+        //mate distance pruning: a mate found closer to the root can't be improved on here
+        let alpha = alpha.max(-MATE + ply_from_root as i16);
+        let beta = beta.min(MATE - ply_from_root as i16 - 1);
+        if alpha >= beta {
+            return alpha;
+        }
+        //the quiescence search probes the hash table itself
+        if depth == 0 {
+            return self.quiesce(board, ply_from_root, alpha, beta, self.quiesce_checks);
+        }
+        let key = board.get_hash();
+        let tt_value = self.tt.look_up_pos(key, board).map(|mut entry| {
+            entry.eval = score_from_tt(entry.eval, ply_from_root);
+            return entry;
+        });
+        let tt_move = if tt_value.is_some() {
+            tt_value.unwrap().best_move
+        } else {
+            ChessMove::default()
+        };
+        //a search with a move excluded has a different result than the one stored
+        let is_singular_search = excluded != ChessMove::default();
+        if !is_singular_search && tt_value.is_some() && (depth <= tt_value.unwrap().depth) {
+            self.stats.tt_hits += 1;
+            match tt_value.unwrap().entry_type {
+                EntryType::Exact => return tt_value.unwrap().eval,
+                EntryType::UpperBound => {
+                    if tt_value.unwrap().eval <= alpha {
+                        return alpha;
+                    }
+                }
+                EntryType::LowerBound => {
+                    if tt_value.unwrap().eval >= beta {
+                        return beta;
+                    }
+                }
+                EntryType::None => {}
             }
-            if !is_capture {
-                tt.store_killer(
-                    ply_from_root as usize,
-                    depth as i32,
-                    mv,
-                    piece,
-                    board.side_to_move().to_index(),
+        }
+        let in_check = board.checkers() != 0;
+        //everything but the principal variation is searched with a zero window
+        let is_pv = beta as i32 - alpha as i32 > 1;
+        //the evaluation stored with the hash entry saves calling evaluate again
+        let static_eval = if in_check {
+            ALPHA
+        } else if tt_value.is_some() && tt_value.unwrap().static_eval != ALPHA {
+            tt_value.unwrap().static_eval
+        } else {
+            evaluate(board, &mut self.td.pawn_table, &mut self.stats)
+        };
+        //reverse futility pruning: far enough above beta that a quiet move won't lose it all
+        if !is_pv
+            && !in_check
+            && !is_singular_search
+            && (depth as usize) < REVERSE_FUTILITY_MARGIN.len()
+            && beta.abs() < MATE_BOUND
+            && static_eval - REVERSE_FUTILITY_MARGIN[depth as usize] >= beta
+        {
+            return beta;
+        }
+        //razoring: so far below alpha that only captures could save the position
+        if !is_pv
+            && !in_check
+            && !is_singular_search
+            && (depth as usize) < RAZOR_MARGIN.len()
+            && static_eval + RAZOR_MARGIN[depth as usize] <= alpha
+            && self.quiesce(board, ply_from_root, alpha, alpha + 1, self.quiesce_checks) <= alpha
+        {
+            return alpha;
+        }
+        //null move pruning: if passing the turn still fails high, the position is good enough
+        if null_allowed
+            && !is_singular_search
+            && depth >= NULL_MOVE_MIN_DEPTH
+            && !in_check
+            && beta < MATE_BOUND
+            && board.has_non_pawn_material(board.side_to_move())
+            && static_eval >= beta
+        {
+            let r = if depth > NULL_MOVE_DEEP_DEPTH { 3 } else { 2 };
+            let null_board = board.make_null_move().unwrap();
+            self.hash_stack.push(null_board.get_hash());
+            self.move_stack[ply_from_root as usize] = None;
+            let score = -self.alpha_beta(
+                &null_board,
+                ply_from_root + 1,
+                depth - 1 - r,
+                extended,
+                -beta,
+                -beta + 1,
+                prev_piece,
+                prev_sq,
+                false,
+                ChessMove::default(),
+            );
+            self.hash_stack.pop();
+            if score == NEG_SEARCH_EXIT_KEY {
+                return SEARCH_EXIT_KEY;
+            }
+            if score >= beta {
+                if depth < NULL_MOVE_VERIFICATION_DEPTH {
+                    return beta;
+                }
+                //zugzwang guard: without the null move the side to move must still fail high
+                let verified = self.alpha_beta(
+                    board,
+                    ply_from_root,
+                    depth - 1 - r,
+                    extended,
+                    beta - 1,
+                    beta,
                     prev_piece,
                     prev_sq,
+                    false,
+                    ChessMove::default(),
                 );
+                if verified == SEARCH_EXIT_KEY {
+                    return SEARCH_EXIT_KEY;
+                }
+                if verified >= beta {
+                    return beta;
+                }
             }
-            tt.set_pos(key, score, EntryType::LowerBound, depth, mv, piece);
-            return beta;
         }
-        if score > alpha {
-            alpha = score;
-            tt_type = EntryType::Exact;
-            best_move = mv;
-            best_move_piece = piece;
+
+        let color = board.side_to_move().to_index();
+        let continuations = self.continuations(ply_from_root);
+        let mut picker = MovePicker::new(
+            tt_move,
+            self.td.history.get_killers(ply_from_root as usize),
+            self.td.history.counters[color][prev_piece.to_index()][prev_sq.to_index()],
+            continuations,
+        );
+        let mut best_move = ChessMove::default();
+        let mut alpha = alpha;
+        let mut tt_type = EntryType::UpperBound;
+        //moves tried so far, pruned ones included
+        let mut i = 0;
+        //searched moves that didn't cut off, they get a history malus when another move does
+        let mut quiets_tried: Vec<(Piece, Square)> = Vec::new();
+        let mut captures_tried: Vec<(Piece, Square, Piece)> = Vec::new();
+        while let Some((mv, move_type)) = picker.next(&board.board, &self.td.history) {
+            if mv == excluded {
+                continue;
+            }
+            if i == 0 {
+                best_move = mv;
+            }
+            let piece = board.piece_on(mv.get_source()).unwrap();
+            let is_capture = board.piece_on(mv.get_dest()).is_some();
+            let new_board = board.make_move_new(mv);
+            let is_quiet = is_quiet(&board.board, mv);
+            let gives_check = new_board.checkers() != 0;
+            //late move pruning: well ordered quiets this late rarely matter near the leaves
+            if i > 0
+                && is_quiet
+                && !is_pv
+                && !in_check
+                && !gives_check
+                && (depth as usize) < LATE_MOVE_COUNT.len()
+                && i >= LATE_MOVE_COUNT[depth as usize]
+            {
+                i += 1;
+                continue;
+            }
+            let rank = mv.get_dest().get_rank();
+            let mut extention = 0;
+            //at most one extension every other ply, so long checking sequences can't run away
+            if extended as u16 * 2 <= ply_from_root as u16
+                && (gives_check
+                    || (piece == Piece::Pawn && (rank == Rank::Second || rank == Rank::Seventh))
+                    || (move_type == MoveType::HashMove
+                        && self.is_singular(
+                            board,
+                            ply_from_root,
+                            depth,
+                            extended,
+                            tt_value,
+                            prev_piece,
+                            prev_sq,
+                        )))
+            {
+                extention = 1;
+            }
+            let mut reduction: u8 = 0;
+            if extention == 0 && is_quiet && depth >= LMR_MIN_DEPTH && i >= LMR_MIN_MOVES {
+                let mut r = get_lmr_reduction(depth, i + 1) as i16;
+                //quiet moves with a bad history or that hang material are likely bad
+                let history =
+                    self.td
+                        .history
+                        .quiet_score(color, piece, mv.get_dest(), &continuations);
+                r -= (history / LMR_HISTORY_DIVISOR) as i16;
+                if see(&board.board, mv) < 0 {
+                    r += 1;
+                }
+                if move_type == MoveType::KillerMove || move_type == MoveType::CounterMove {
+                    r -= 1;
+                }
+                if gives_check {
+                    r -= 1;
+                }
+                if is_pv {
+                    r -= 1;
+                }
+                //the reduced search keeps at least one ply
+                reduction = r.clamp(0, depth as i16 - 2) as u8;
+            }
+            self.hash_stack.push(new_board.get_hash());
+            self.move_stack[ply_from_root as usize] = Some((piece, mv.get_dest()));
+            //principal variation search: the first move gets the full window, the rest only
+            //have to prove they're worse than it and are searched again if they aren't
+            let mut score = 0;
+            if i != 0 {
+                score = -self.alpha_beta(
+                    &new_board,
+                    ply_from_root + 1,
+                    depth + extention - 1 - reduction,
+                    extended + extention,
+                    -alpha - 1,
+                    -alpha,
+                    piece,
+                    mv.get_dest(),
+                    true,
+                    ChessMove::default(),
+                );
+                if score > alpha && reduction != 0 {
+                    score = -self.alpha_beta(
+                        &new_board,
+                        ply_from_root + 1,
+                        depth + extention - 1,
+                        extended + extention,
+                        -alpha - 1,
+                        -alpha,
+                        piece,
+                        mv.get_dest(),
+                        true,
+                        ChessMove::default(),
+                    );
+                }
+            }
+            if i == 0 || (score > alpha && score < beta) {
+                score = -self.alpha_beta(
+                    &new_board,
+                    ply_from_root + 1,
+                    depth + extention - 1,
+                    extended + extention,
+                    -beta,
+                    -alpha,
+                    piece,
+                    mv.get_dest(),
+                    true,
+                    ChessMove::default(),
+                );
+            }
+            self.hash_stack.pop();
+            if score == NEG_SEARCH_EXIT_KEY {
+                return SEARCH_EXIT_KEY;
+            }
+            if score >= beta {
+                self.stats.beta_cutoffs += 1;
+                if is_singular_search {
+                    return beta;
+                }
+                let bonus = history_bonus(depth);
+                if is_quiet {
+                    self.td.history.store_killer(
+                        ply_from_root as usize,
+                        mv,
+                        color,
+                        prev_piece,
+                        prev_sq,
+                    );
+                    self.td.history.update_quiet(
+                        color,
+                        piece,
+                        mv.get_dest(),
+                        &continuations,
+                        bonus,
+                    );
+                    for (p, to) in quiets_tried {
+                        self.td
+                            .history
+                            .update_quiet(color, p, to, &continuations, -bonus);
+                    }
+                } else if is_capture {
+                    let captured = board.piece_on(mv.get_dest()).unwrap();
+                    self.td
+                        .history
+                        .update_capture(piece, mv.get_dest(), captured, bonus);
+                }
+                for (p, to, captured) in captures_tried {
+                    self.td.history.update_capture(p, to, captured, -bonus);
+                }
+                self.tt.set_pos(
+                    key,
+                    score_to_tt(score, ply_from_root),
+                    static_eval,
+                    EntryType::LowerBound,
+                    depth,
+                    mv,
+                    is_pv,
+                );
+                return beta;
+            }
+            if score > alpha {
+                alpha = score;
+                tt_type = EntryType::Exact;
+                best_move = mv;
+            }
+            if is_quiet {
+                quiets_tried.push((piece, mv.get_dest()));
+            } else if is_capture {
+                captures_tried.push((piece, mv.get_dest(), board.piece_on(mv.get_dest()).unwrap()));
+            }
+            i += 1;
         }
-        if moves[i].2 == MoveType::HashMove {
-            let mut iterable = MoveGen::new_legal(&board.board);
-            moves = sort_moves(
-                &mut iterable,
-                &board.board,
-                tt_move,
-                tt.get_killers(ply_from_root as usize),
-                &tt.history[board.side_to_move().to_index()],
-                tt.counters[board.side_to_move().to_index()][prev_piece.to_index()]
-                    [prev_sq.to_index()],
-            );
+        if i == 0 {
+            //the excluded move was the only legal one, so it is singular
+            if is_singular_search {
+                return alpha;
+            }
+            if in_check {
+                return -MATE + ply_from_root as i16;
+            }
+            return self.draw_score(ply_from_root);
+        }
+        if is_singular_search {
+            return alpha;
         }
-        i += 1;
+        self.tt.set_pos(
+            key,
+            score_to_tt(alpha, ply_from_root),
+            static_eval,
+            tt_type,
+            depth,
+            best_move,
+            is_pv,
+        );
+        return alpha;
     }
-    tt.set_pos(key, alpha, tt_type, depth, best_move, best_move_piece);
-    return alpha;
-}
-fn search(
-    board: &Position,
-    moves: &mut Vec<(ChessMove, i16)>,
-    mut alpha: i16,
-    _beta: i16,
-    max_depth: u8,
-    init: &Instant,
-    tt: &mut TranspositionTable,
-    draws: &Vec<u64>,
-) -> SearchResult {
-    let start = Instant::now();
-    let mut best_move = moves[0].0;
-    for i in 0..moves.len() {
-        let (mv, _prev) = moves[i];
-        let piece = board.piece_on(mv.get_source()).unwrap();
-        let new_board = board.make_move_new(mv);
-        let mut score = 0;
-        if !draws.contains(&new_board.get_hash()) {
-            let mut extention = if board.checkers() != 0 { 1 } else { 0 };
+    fn search(
+        &mut self,
+        board: &Position,
+        moves: &mut Vec<(ChessMove, i16)>,
+        mut alpha: i16,
+        beta: i16,
+        max_depth: u8,
+    ) -> SearchResult {
+        let multi_pv = self.multi_pv;
+        let start = Instant::now();
+        let mut best_move = moves[0].0;
+        let root_alpha = alpha;
+        let mut tt_type = EntryType::UpperBound;
+        //scores of the best lines so far, the top multi_pv moves need exact scores
+        let mut line_scores: Vec<i16> = Vec::with_capacity(multi_pv + 1);
+        for i in 0..moves.len() {
+            let (mv, _prev) = moves[i];
+            let line_alpha = if line_scores.len() < multi_pv {
+                root_alpha
+            } else {
+                line_scores[multi_pv - 1]
+            };
+            let piece = board.piece_on(mv.get_source()).unwrap();
+            let new_board = board.make_move_new(mv);
+            self.hash_stack.push(new_board.get_hash());
+            self.move_stack[0] = Some((piece, mv.get_dest()));
             let rank = mv.get_dest().get_rank();
-            if piece == Piece::Pawn && (rank == Rank::Second || rank == Rank::Seventh) {
-                extention += 1;
-            }
+            let extention = if new_board.checkers() != 0
+                || (piece == Piece::Pawn && (rank == Rank::Second || rank == Rank::Seventh))
+            {
+                1
+            } else {
+                0
+            };
             let reduction = if i > 4 && extention == 0 && max_depth > 2 {
                 1
             } else {
                 0
             };
-            let mut needs_full_search = true;
-            if reduction == 1 {
-                score = -alpha_beta(
+            //once the multi_pv lines are filled the other moves get a zero window first
+            let full_window = line_scores.len() < multi_pv;
+            let mut score = 0;
+            if !full_window {
+                score = -self.alpha_beta(
                     &new_board,
                     1,
-                    max_depth - 1 - reduction,
+                    max_depth - 1 + extention - reduction,
                     extention,
-                    -BETA,
-                    -alpha,
-                    init,
-                    tt,
+                    -line_alpha - 1,
+                    -line_alpha,
                     piece,
                     mv.get_dest(),
+                    true,
+                    ChessMove::default(),
                 );
-                needs_full_search = score > alpha;
+                if score > line_alpha && reduction != 0 {
+                    score = -self.alpha_beta(
+                        &new_board,
+                        1,
+                        max_depth - 1 + extention,
+                        extention,
+                        -line_alpha - 1,
+                        -line_alpha,
+                        piece,
+                        mv.get_dest(),
+                        true,
+                        ChessMove::default(),
+                    );
+                }
             }
-            if needs_full_search {
-                score = -alpha_beta(
+            if full_window || (score > line_alpha && score < beta) {
+                score = -self.alpha_beta(
                     &new_board,
                     1,
                     max_depth - 1 + extention,
                     extention,
-                    -BETA,
-                    -alpha,
-                    init,
-                    tt,
+                    -beta,
+                    -line_alpha,
                     piece,
                     mv.get_dest(),
+                    true,
+                    ChessMove::default(),
                 );
             }
+            self.hash_stack.pop();
+            if score == (NEG_SEARCH_EXIT_KEY) {
+                return SearchResult {
+                    eval: alpha,
+                    best_move,
+                    depth: max_depth - 1,
+                    duration: start.elapsed(),
+                    nodes: 0,
+                };
+            }
+            moves[i] = (mv, score);
+            let pos = line_scores.partition_point(|s| *s >= score);
+            line_scores.insert(pos, score);
+            line_scores.truncate(multi_pv);
+            if score >= beta {
+                //fail high, the aspiration window has to be widened
+                alpha = beta;
+                best_move = mv;
+                tt_type = EntryType::LowerBound;
+                break;
+            }
+            if score > alpha {
+                alpha = score;
+                best_move = mv;
+                tt_type = EntryType::Exact;
+            }
         }
-
-        if score == (NEG_SEARCH_EXIT_KEY) {
+        moves.sort_by(|b, a| a.1.cmp(&b.1));
+        let static_eval = if board.checkers() != 0 {
+            ALPHA
+        } else {
+            evaluate(board, &mut self.td.pawn_table, &mut self.stats)
+        };
+        self.tt.set_pos(
+            board.get_hash(),
+            alpha,
+            static_eval,
+            tt_type,
+            max_depth,
+            best_move,
+            true,
+        );
+        return SearchResult {
+            eval: alpha,
+            best_move,
+            depth: max_depth,
+            duration: start.elapsed(),
+            nodes: 0,
+        };
+    }
+    //searches a window around the previous iteration's score, widening it on the side the
+    //score falls out of until it lands inside
+    fn aspiration_search(
+        &mut self,
+        board: &Position,
+        moves: &mut Vec<(ChessMove, i16)>,
+        prev_eval: i16,
+        depth: u8,
+    ) -> SearchResult {
+        let mut delta = ASPIRATION_WINDOW;
+        let mut alpha = (prev_eval as i32 - delta).max(ALPHA as i32) as i16;
+        let mut beta = (prev_eval as i32 + delta).min(BETA as i32) as i16;
+        loop {
+            let result = self.search(board, moves, alpha, beta, depth);
+            if result.depth != depth {
+                return result;
+            }
+            delta *= 2;
+            if delta > MAX_ASPIRATION_WINDOW {
+                delta = i16::MAX as i32;
+            }
+            if result.eval <= alpha && alpha != ALPHA {
+                alpha = (alpha as i32 - delta).max(ALPHA as i32) as i16;
+            } else if result.eval >= beta && beta != BETA {
+                beta = (beta as i32 + delta).min(BETA as i32) as i16;
+            } else {
+                return result;
+            }
+        }
+    }
+    //prints one info line for each of the best multi_pv root moves
+    fn print_info(&self, board: &Position, result: &SearchResult, moves: &[(ChessMove, i16)]) {
+        let millis = result.duration.as_millis() as u64;
+        let hashfull = self.tt.hashfull();
+        let nodes = self.total_nodes();
+        for (k, (mv, score)) in moves.iter().take(self.multi_pv).enumerate() {
+            let mut pv = vec![*mv];
+            pv.extend(self.tt.get_pv(&board.make_move_new(*mv)));
+            println!(
+                "info depth {} seldepth {} multipv {} score {} nodes {} nps {} time {} hashfull {} pv {}",
+                result.depth,
+                self.stats.seldepth,
+                k + 1,
+                uci_score(*score),
+                nodes,
+                nodes * 1000 / millis.max(1),
+                millis,
+                hashfull,
+                pv.iter()
+                    .map(|m| m.to_string())
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            );
+        }
+        if self.debug {
+            println!(
+                "info string tt_hits {} pawn_tt_hits {} cut_offs {}",
+                self.stats.tt_hits, self.stats.pawn_tt_hits, self.stats.beta_cutoffs
+            );
+        }
+    }
+    pub fn start_search(
+        &mut self,
+        board: &Position,
+        limits: &SearchLimits,
+        game_hashes: &[u64],
+    ) -> SearchResult {
+        //a mate in n moves is found within 2n-1 plies
+        let max_depth = match limits.mate {
+            Some(n) => limits
+                .depth
+                .min((2 * n as u16).saturating_sub(1).clamp(1, MAX_DEPTH as u16) as u8),
+            None => limits.depth,
+        };
+        if self.is_main() {
+            match limits.time {
+                Some(tm) => self.control.set_time_limits(tm.soft, tm.hard),
+                None => self.control.set_time_limits(Duration::MAX, Duration::MAX),
+            }
+            self.node_limit = limits.nodes.unwrap_or(u64::MAX);
+        }
+        self.stats = SearchStats::default();
+        self.start = Instant::now();
+        //only positions since the last irreversible move can repeat
+        let since_irreversible = (board.halfmove_clock() as usize).min(game_hashes.len());
+        self.hash_stack = game_hashes[(game_hashes.len() - since_irreversible)..].to_vec();
+        self.hash_stack.push(board.get_hash());
+        self.root_index = self.hash_stack.len() - 1;
+        let mut picker = MovePicker::new(
+            ChessMove::default(),
+            &self.td.history.default_killers,
+            ChessMove::default(),
+            [None, None],
+        );
+        let mut moves: Vec<(ChessMove, i16)> = Vec::new();
+        while let Some((mv, _)) = picker.next(&board.board, &self.td.history) {
+            if limits.search_moves.is_empty() || limits.search_moves.contains(&mv) {
+                moves.push((mv, 0));
+            }
+        }
+        //checkmate, stalemate or no legal searchmoves, there is nothing to search and the null
+        //move is reported
+        if moves.is_empty() {
+            let eval = if board.checkers() != 0 {
+                -MATE
+            } else {
+                self.draw_score(0)
+            };
             return SearchResult {
-                eval: alpha,
-                best_move,
-                depth: max_depth,
-                duration: start.elapsed(),
+                eval,
+                best_move: ChessMove::default(),
+                depth: 0,
+                duration: self.start.elapsed(),
                 nodes: 0,
             };
         }
-        moves[i] = (mv, score);
-        if score > alpha {
-            alpha = score;
-            best_move = mv;
-        }
-    }
-    moves.sort_by(|b, a| a.1.cmp(&b.1));
-    tt.set_pos(
-        board.get_hash(),
-        alpha,
-        EntryType::Exact,
-        max_depth,
-        best_move,
-        board.piece_on(best_move.get_source()).unwrap(),
-    );
-    return SearchResult {
-        eval: alpha,
-        best_move,
-        depth: max_depth,
-        duration: start.elapsed(),
-        nodes: 0,
-    };
+        let alpha = ALPHA;
+        let beta = BETA;
+        let mut result = self.search(board, &mut moves, alpha, beta, 1);
+        result.duration = self.start.elapsed();
+        if self.is_main() {
+            self.print_info(board, &result, &moves);
+        }
+        if moves.len() == 1 || mate_found(&result, limits.mate) {
+            result.nodes = self.total_nodes();
+            return result;
+        }
+        //number of iterations the best move hasn't changed for
+        let mut stability = 0;
+        for i in 2..=max_depth {
+            self.stats.tt_hits = 0;
+            self.stats.pawn_tt_hits = 0;
+            self.stats.beta_cutoffs = 0;
+            //half of the helpers search one ply deeper so the threads don't all walk the same tree
+            let depth = if self.id % 2 == 1 {
+                (i + 1).min(max_depth)
+            } else {
+                i
+            };
+            //multipv needs exact scores for more than one move, so it searches full windows
+            let res = if self.multi_pv == 1
+                && depth >= ASPIRATION_MIN_DEPTH
+                && result.eval.abs() < MATE_BOUND
+            {
+                self.aspiration_search(board, &mut moves, result.eval, depth)
+            } else {
+                self.search(board, &mut moves, alpha, beta, depth)
+            };
+            let old_alpha = result.eval;
+            let old_best_move = result.best_move;
+            //an aborted iteration only has a bound from its window and a move that may not have
+            //been searched to the end, the previous iteration's result stands
+            if res.depth == depth {
+                result = res;
+            }
+            result.duration = self.start.elapsed();
+            if !self.is_main() {
+                if self.control.is_stopped() {
+                    break;
+                }
+                continue;
+            }
+            if result.depth == i {
+                self.print_info(board, &result, &moves);
+                if result.best_move == old_best_move {
+                    stability += 1;
+                } else {
+                    stability = 0;
+                }
+            }
+            if self.control.is_stopped()
+                || self.time_is_up()
+                || (!self.control.is_infinite()
+                    && self.start.elapsed().as_millis() as u64
+                        >= iteration_limit(
+                            self.control.soft_time_limit.load(Ordering::Relaxed),
+                            self.control.time_limit.load(Ordering::Relaxed),
+                            stability,
+                            old_alpha.saturating_sub(result.eval),
+                        ))
+                || self.node_limit_reached()
+                || mate_found(&result, limits.mate)
+            {
+                break;
+            }
+        }
+        self.node_counters[self.id]
+            .0
+            .store(self.stats.nodes, Ordering::Relaxed);
+        result.nodes = self.total_nodes();
+        result.duration = self.start.elapsed();
+        return result;
+    }
 }
+//lazy smp: every thread searches the same position and they share work only through the
+//transposition table, the main thread (the first one) reports and picks the move
 pub fn start_search(
     board: &Position,
-    max_depth: u8,
-    max_duration: Duration,
-    tt: &mut TranspositionTable,
-    draws: &Vec<u64>,
-    log: bool,
+    limits: &SearchLimits,
+    game_hashes: &[u64],
+    tt: &TranspositionTable,
+    threads: &mut [ThreadData],
+    control: &SearchControl,
+    options: SearchOptions,
 ) -> SearchResult {
-    unsafe {
-        TIME_LIMIT = max_duration;
-    }
-    let start = Instant::now();
-    let mut iterable = MoveGen::new_legal(&board.board);
-    let mut moves: Vec<(ChessMove, i16)> = sort_moves(
-        &mut iterable,
-        &board.board,
-        ChessMove::default(),
-        &tt.default_killers,
-        &tt.history[board.side_to_move().to_index()],
-        ChessMove::default(),
-    )
-    .iter()
-    .map(|i| (i.0, i.1 as i16))
-    .collect::<Vec<(ChessMove, i16)>>();
-    let alpha = ALPHA;
-    let beta = BETA;
-    let mut result = search(board, &mut moves, alpha, beta, 1, &start, tt, draws);
-    if moves.len() == 1 {
+    let node_counters: Vec<NodeCounter> = threads.iter().map(|_| NodeCounter::default()).collect();
+    let (main, helpers) = threads.split_first_mut().unwrap();
+    return std::thread::scope(|scope| {
+        for (i, td) in helpers.iter_mut().enumerate() {
+            let node_counters = &node_counters;
+            std::thread::Builder::new()
+                .stack_size(SEARCH_STACK_SIZE)
+                .spawn_scoped(scope, move || {
+                    Searcher::new(tt, td, control, node_counters, i + 1, options).start_search(
+                        board,
+                        limits,
+                        game_hashes,
+                    );
+                })
+                .unwrap();
+        }
+        let result = Searcher::new(tt, main, control, &node_counters, 0, options).start_search(
+            board,
+            limits,
+            game_hashes,
+        );
+        //bestmove can't be sent before stop or ponderhit
+        while control.is_infinite() && !control.is_stopped() {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        control.stop.store(true, Ordering::Relaxed);
         return result;
+    });
+}
+//converts the internal score into a uci score, mates are counted in moves rather than plies
+fn uci_score(eval: i16) -> String {
+    if eval > MATE_BOUND {
+        return format!("mate {}", (MATE - eval + 1) / 2);
     }
-    let mut sum = 0;
-    for i in 2..=max_depth {
-        unsafe {
-            TT_HITS = 0;
-            sum += NODES;
-            NODES = 0;
-            PAWN_TT_HITS = 0;
-            BETA_CUTOFFS = 0;
-        }
-        let res = search(board, &mut moves, alpha, beta, i, &start, tt, draws);
-        let old_alpha = result.eval;
-        result = res;
-        if log {
-            unsafe {
-                println!(
-                    "info depth {} bestmove {} ({}) tt_hits: {} pawn_tt_hits: {} cut_offs: {} nodes {}, {:?}",
-                    i,
-                    result.best_move.to_string(),
-                    if result.eval == ALPHA{old_alpha}else{result.eval},
-                    TT_HITS,
-                    PAWN_TT_HITS,
-                    BETA_CUTOFFS,
-                    NODES,
-                    start.elapsed()
-                );
-            }
+    if eval < -MATE_BOUND {
+        return format!("mate -{}", (MATE + eval) / 2);
+    }
+    return format!("cp {}", eval);
+}
+//mate scores are stored in the hash table as the distance from the stored position, so they
+//stay right when the position is reached again at a different ply
+fn score_to_tt(eval: i16, ply_from_root: u8) -> i16 {
+    if eval > MATE_BOUND {
+        return eval + ply_from_root as i16;
+    }
+    if eval < -MATE_BOUND {
+        return eval - ply_from_root as i16;
+    }
+    return eval;
+}
+fn score_from_tt(eval: i16, ply_from_root: u8) -> i16 {
+    if eval > MATE_BOUND {
+        return eval - ply_from_root as i16;
+    }
+    if eval < -MATE_BOUND {
+        return eval + ply_from_root as i16;
+    }
+    return eval;
+}
+//go mate n stops as soon as a mate in n or less is proven
+fn mate_found(result: &SearchResult, mate: Option<u8>) -> bool {
+    return match mate {
+        Some(n) => result.eval > MATE_BOUND && (MATE - result.eval + 1) / 2 <= n as i16,
+        None => false,
+    };
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::init;
+    use std::str::FromStr;
+    //searches the position after the moves with a fresh hash table and one thread, on a thread
+    //with the search's stack size
+    fn search_position(
+        fen: &str,
+        moves: &[&str],
+        limits: SearchLimits,
+        options: SearchOptions,
+    ) -> (ChessMove, i16, u64) {
+        init();
+        let mut board = Position::new(fen);
+        let mut game_hashes = Vec::new();
+        for m in moves {
+            game_hashes.push(board.get_hash());
+            board = board.make_move_new(ChessMove::from_str(m).unwrap());
         }
-        if start.elapsed() >= max_duration {
-            if result.eval == ALPHA {
-                result.eval = old_alpha;
-            }
-            break;
+        return std::thread::Builder::new()
+            .stack_size(SEARCH_STACK_SIZE)
+            .spawn(move || {
+                let tt = TranspositionTable::init(1);
+                let mut threads = vec![ThreadData::new(1)];
+                let control = SearchControl::default();
+                let res = start_search(
+                    &board,
+                    &limits,
+                    &game_hashes,
+                    &tt,
+                    &mut threads,
+                    &control,
+                    options,
+                );
+                return (res.best_move, res.eval, res.nodes);
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+    fn with_contempt(contempt: i16) -> SearchOptions {
+        return SearchOptions {
+            contempt,
+            multi_pv: 1,
+            quiesce_checks: false,
+            debug: false,
+        };
+    }
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    #[test]
+    fn node_limited_search_is_deterministic() {
+        let limits = || SearchLimits {
+            nodes: Some(5000),
+            ..SearchLimits::default()
+        };
+        let first = search_position(KIWIPETE, &[], limits(), with_contempt(0));
+        assert_eq!(first, search_position(KIWIPETE, &[], limits(), with_contempt(0)));
+        assert!(first.2 >= 5000 && first.2 < 6000);
+    }
+    #[test]
+    fn depth_limited_search_is_deterministic() {
+        let limits = || SearchLimits {
+            depth: 4,
+            ..SearchLimits::default()
+        };
+        assert_eq!(
+            search_position(KIWIPETE, &[], limits(), with_contempt(0)),
+            search_position(KIWIPETE, &[], limits(), with_contempt(0))
+        );
+    }
+    #[test]
+    fn long_mate_searches_are_limited_by_depth_only() {
+        let limits = |mate| SearchLimits {
+            depth: 3,
+            mate,
+            ..SearchLimits::default()
+        };
+        let unlimited = search_position(KIWIPETE, &[], limits(None), with_contempt(0));
+        for n in [2, 128, 255] {
+            assert_eq!(
+                search_position(KIWIPETE, &[], limits(Some(n)), with_contempt(0)),
+                unlimited
+            );
         }
     }
-    result.nodes = sum;
-    result.duration = start.elapsed();
-    return result;
+    #[test]
+    fn search_without_legal_moves_returns_the_null_move() {
+        let limits = || SearchLimits {
+            depth: 4,
+            ..SearchLimits::default()
+        };
+        //checkmated
+        let mated = "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3";
+        assert_eq!(
+            search_position(mated, &[], limits(), with_contempt(0)),
+            (ChessMove::default(), -MATE, 0)
+        );
+        //stalemated
+        let stalemated = "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1";
+        assert_eq!(
+            search_position(stalemated, &[], limits(), with_contempt(0)),
+            (ChessMove::default(), 0, 0)
+        );
+    }
+    #[test]
+    fn quiescence_checks_are_searched_when_enabled() {
+        let limits = || SearchLimits {
+            depth: 6,
+            ..SearchLimits::default()
+        };
+        let options = SearchOptions {
+            quiesce_checks: true,
+            ..with_contempt(0)
+        };
+        let without = search_position(KIWIPETE, &[], limits(), with_contempt(0));
+        let with = search_position(KIWIPETE, &[], limits(), options);
+        assert_ne!(without.2, with.2);
+        assert_eq!(with, search_position(KIWIPETE, &[], limits(), options));
+    }
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    //black can go back to the starting position a third time, a draw by repetition
+    const REPEATING: [&str; 7] = ["g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1"];
+    #[test]
+    fn contempt_decides_whether_to_repeat() {
+        let limits = || SearchLimits {
+            depth: 4,
+            ..SearchLimits::default()
+        };
+        let repeat = ChessMove::from_str("f6g8").unwrap();
+        //a negative contempt prefers the draw, worth -contempt to the side at the root
+        let (mv, eval, _) = search_position(START, &REPEATING, limits(), with_contempt(-200));
+        assert_eq!((mv, eval), (repeat, 200));
+        //a positive contempt avoids it
+        let (mv, eval, _) = search_position(START, &REPEATING, limits(), with_contempt(200));
+        assert_ne!(mv, repeat);
+        assert!(eval > -200);
+    }
 }
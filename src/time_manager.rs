@@ -0,0 +1,64 @@
+use std::time::Duration;
+//moves left to plan for when the gui doesn't send movestogo
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+const MAX_MOVES_TO_GO: u32 = 50;
+//the hard limit may stretch the soft limit this many times
+const HARD_LIMIT_SCALE: u32 = 4;
+//the soft limit is multiplied by this while the best move keeps changing, indexed by the
+//number of iterations the best move has been stable for, in percent
+const STABILITY_SCALE: [u64; 6] = [160, 130, 110, 90, 75, 60];
+//score drop (centipawns) at which the soft limit is doubled
+const MAX_SCORE_DROP: u64 = 100;
+#[derive(Clone, Copy)]
+pub struct TimeManager {
+    //after this the search won't start a new iteration
+    pub soft: Duration,
+    //after this the search is aborted
+    pub hard: Duration,
+}
+impl TimeManager {
+    pub fn new(
+        time_left: Duration,
+        inc: Duration,
+        moves_to_go: Option<u32>,
+        overhead: Duration,
+    ) -> TimeManager {
+        let time_left = time_left.saturating_sub(overhead);
+        let moves_to_go = moves_to_go
+            .unwrap_or(DEFAULT_MOVES_TO_GO)
+            .clamp(1, MAX_MOVES_TO_GO);
+        //never plan to use more than what's left, keeping a reserve for the next moves
+        let max_time = if moves_to_go == 1 {
+            time_left * 9 / 10
+        } else {
+            time_left * 3 / 4
+        };
+        let hard = ((time_left / moves_to_go + inc * 3 / 4) * HARD_LIMIT_SCALE).min(max_time);
+        let soft = (time_left / moves_to_go + inc * 3 / 4).min(hard);
+        return TimeManager {
+            soft: soft.max(Duration::from_millis(1)),
+            hard: hard.max(Duration::from_millis(1)),
+        };
+    }
+    //go movetime: spend exactly the given time
+    pub fn fixed(movetime: Duration, overhead: Duration) -> TimeManager {
+        let time = movetime
+            .saturating_sub(overhead)
+            .max(Duration::from_millis(1));
+        return TimeManager {
+            soft: time,
+            hard: time,
+        };
+    }
+}
+//time (ms) after which no new iteration is started, a stable best move shrinks the soft
+//limit and a falling score extends it, but never past the hard limit
+pub fn iteration_limit(soft: u64, hard: u64, stability: usize, score_drop: i16) -> u64 {
+    if soft >= hard {
+        return hard;
+    }
+    let drop = (score_drop.max(0) as u64).min(MAX_SCORE_DROP);
+    let scale = STABILITY_SCALE[stability.min(STABILITY_SCALE.len() - 1)];
+    let limit = soft * scale / 100 * (MAX_SCORE_DROP + drop) / MAX_SCORE_DROP;
+    return limit.min(hard);
+}
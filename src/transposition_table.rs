@@ -1,14 +1,21 @@
-use chess::{ChessMove, Piece, Square};
+use chess::{ChessMove, ALL_PIECES, ALL_SQUARES};
+use std::sync::atomic::{AtomicU16, AtomicU64, Ordering};
 
 use crate::board::Position;
+use crate::moves::is_legal_move;
+use crate::score::Score;
 
-pub const NUM_OF_POSITIONS: usize = 0x400000;
-pub const NUM_OF_PAWNS: usize = 0x40000;
-const KEY: u64 = NUM_OF_POSITIONS as u64 - 1;
-const PAWN_KEY: u64 = NUM_OF_PAWNS as u64 - 1;
-const KILLERS_PER_PLY: usize = 3;
-const KILLER_PLIES: usize = 20;
-pub type Killers = [ChessMove; KILLERS_PER_PLY];
+pub const DEFAULT_HASH_MB: usize = 64;
+pub const DEFAULT_PAWN_HASH_MB: usize = 8;
+pub const MAX_HASH_MB: usize = 65536;
+pub const MAX_PAWN_HASH_MB: usize = 1024;
+const MAX_PV_LENGTH: usize = 64;
+//entries sharing one 32 byte cluster, a position may be stored in any of them
+const CLUSTER_SIZE: usize = 3;
+//generations wrap around at 64, they only have 6 bits
+const GENERATION_MASK: u8 = 63;
+//each search an entry is old counts as this many plies of depth when replacing
+const AGE_WEIGHT: i32 = 8;
 
 #[derive(PartialEq, Clone, Copy)]
 pub enum EntryType {
@@ -19,138 +26,228 @@ pub enum EntryType {
 }
 #[derive(Clone, Copy)]
 pub struct PositionEntry {
-    pub key: u64,
     pub eval: i16,
+    //evaluation of the position itself, -i16::MAX when it was stored in check
+    pub static_eval: i16,
     pub entry_type: EntryType,
     pub depth: u8,
     pub best_move: ChessMove,
-    pub piece: Piece,
+}
+//a 10 byte entry is a 16 bit key check and 64 bits of data:
+//move 16 | eval 16 | static eval 16 | depth 8 | entry type 2 | generation 6
+//a move is from 6 | to 6 | promotion 3, the null move (a1a1) means there is none
+impl PositionEntry {
+    fn pack(&self, generation: u8) -> u64 {
+        let promotion = match self.best_move.get_promotion() {
+            Some(p) => p.to_index() as u64,
+            None => 0,
+        };
+        let mv = self.best_move.get_source().to_index() as u64
+            | (self.best_move.get_dest().to_index() as u64) << 6
+            | promotion << 12;
+        return mv
+            | (self.eval as u16 as u64) << 16
+            | (self.static_eval as u16 as u64) << 32
+            | (self.depth as u64) << 48
+            | (self.entry_type as u64) << 56
+            | ((generation & GENERATION_MASK) as u64) << 58;
+    }
+    fn unpack(data: u64) -> PositionEntry {
+        let promotion = (data >> 12) & 7;
+        return PositionEntry {
+            eval: (data >> 16) as u16 as i16,
+            static_eval: (data >> 32) as u16 as i16,
+            entry_type: match (data >> 56) & 3 {
+                1 => EntryType::Exact,
+                2 => EntryType::LowerBound,
+                3 => EntryType::UpperBound,
+                _ => EntryType::None,
+            },
+            depth: (data >> 48) as u8,
+            best_move: ChessMove::new(
+                ALL_SQUARES[(data & 63) as usize],
+                ALL_SQUARES[((data >> 6) & 63) as usize],
+                if promotion == 0 {
+                    None
+                } else {
+                    Some(ALL_PIECES[promotion as usize])
+                },
+            ),
+        };
+    }
+}
+fn generation_of(data: u64) -> u8 {
+    return (data >> 58) as u8;
+}
+//the key check is the top 16 bits of the hash, stored xored with the data folded to 16 bits
+//so an entry torn by two threads writing at once fails the check
+fn key_check(key: u64, data: u64) -> u16 {
+    return ((key >> 48) ^ data ^ (data >> 16) ^ (data >> 32) ^ (data >> 48)) as u16;
+}
+#[derive(Default)]
+#[repr(C, align(32))]
+struct Cluster {
+    keys: [AtomicU16; CLUSTER_SIZE],
+    data: [AtomicU64; CLUSTER_SIZE],
 }
 #[derive(Clone, Copy, Default)]
 pub struct PawnEntry {
     pub hash: u64,
     pub w_filesets: u8,
     pub b_filesets: u8,
-    pub w_pst: (i16, i16),
-    pub b_pst: (i16, i16),
-    // pub unhealthy_pawns_count: (u8, u8),
-    pub eval: i16,
+    //pawn structure and pawn square tables, white's minus black's
+    pub eval: Score,
 }
+//shared by all search threads, every method but resizing takes &self
 pub struct TranspositionTable {
-    table: Vec<PositionEntry>,
-    pawn_table: Vec<PawnEntry>,
-    killers: [Killers; KILLER_PLIES],
-    pub default_killers: Killers,
-    pub history: [[[i32; 64]; 6]; 2],
-    pub bf_history: [[[i32; 64]; 6]; 2],
-    pub counters: [[[ChessMove; 64]; 6]; 2],
+    table: Vec<Cluster>,
+    key: u64,
+    //bumped by every go, entries written by older searches are replaced first
+    generation: u8,
+}
+//every search thread has its own pawn table
+pub struct PawnTable {
+    table: Vec<PawnEntry>,
+    key: u64,
+}
+//largest power of two number of entries that fits in the given size
+fn entries_for_size<T>(mb: usize) -> usize {
+    let entries = (mb * 1048576 / std::mem::size_of::<T>()).max(1);
+    return 1 << (usize::BITS - 1 - entries.leading_zeros());
 }
 impl TranspositionTable {
     #[inline]
-    pub fn init() -> TranspositionTable {
+    pub fn init(hash_mb: usize) -> TranspositionTable {
         let mut x = TranspositionTable {
-            table: Vec::<PositionEntry>::with_capacity(NUM_OF_POSITIONS),
-            pawn_table: Vec::<PawnEntry>::with_capacity(NUM_OF_PAWNS),
-            killers: [[ChessMove::default(); KILLERS_PER_PLY]; KILLER_PLIES],
-            default_killers: [ChessMove::default(); KILLERS_PER_PLY],
-            history: [[[0; 64]; 6]; 2],
-            bf_history: [[[1; 64]; 6]; 2],
-            counters: [[[ChessMove::default(); 64]; 6]; 2],
+            table: Vec::new(),
+            key: 0,
+            generation: 0,
         };
-        for _i in 0..NUM_OF_POSITIONS {
-            x.table.push(PositionEntry {
-                key: 0,
-                eval: 0,
-                entry_type: EntryType::None,
-                depth: 0,
-                best_move: ChessMove::default(),
-                piece: Piece::King,
-            });
-        }
-        for _i in 0..NUM_OF_PAWNS {
-            x.pawn_table.push(PawnEntry::default());
-        }
+        x.set_hash_size(hash_mb);
         return x;
     }
+    pub fn set_hash_size(&mut self, mb: usize) {
+        let clusters = entries_for_size::<Cluster>(mb);
+        self.table = (0..clusters).map(|_| Cluster::default()).collect();
+        self.key = clusters as u64 - 1;
+    }
+    pub fn capacity(&self) -> usize {
+        return self.table.len() * CLUSTER_SIZE;
+    }
+    pub fn size_in_bytes(&self) -> usize {
+        return self.table.len() * std::mem::size_of::<Cluster>();
+    }
+    pub fn new_search(&mut self) {
+        self.generation = self.generation.wrapping_add(1) & GENERATION_MASK;
+    }
+    //the stored move is checked against the board, a 16 bit key check lets through the odd
+    //collision and a wrong move must never reach make_move
     pub fn look_up_pos(&self, key: u64, board: &Position) -> Option<PositionEntry> {
-        let res = self.table[(key & KEY) as usize];
-        if (res.entry_type == EntryType::None)
-            || (res.key != key)
-            || (board.piece_on(res.best_move.get_source()) != Some(res.piece))
-        {
-            return None;
+        let cluster = &self.table[(key & self.key) as usize];
+        for i in 0..CLUSTER_SIZE {
+            let data = cluster.data[i].load(Ordering::Relaxed);
+            if cluster.keys[i].load(Ordering::Relaxed) != key_check(key, data) {
+                continue;
+            }
+            let res = PositionEntry::unpack(data);
+            if res.entry_type == EntryType::None {
+                continue;
+            }
+            if res.best_move != ChessMove::default() && !is_legal_move(&board.board, res.best_move)
+            {
+                return None;
+            }
+            return Some(res);
         }
-        return Some(res);
+        return None;
     }
+    //replaces the entry of the same position if there is one, else the one that is worth the
+    //least: empty entries first, then shallow entries from old searches. an entry of the same
+    //position is only overwritten by an at least as deep result, a result from a pv node, or
+    //when it is from an older search, so quiescence stores can't wipe out deep entries
+    #[allow(clippy::too_many_arguments)]
     pub fn set_pos(
-        &mut self,
+        &self,
         key: u64,
         eval: i16,
+        static_eval: i16,
         entry_type: EntryType,
         depth: u8,
         best_move: ChessMove,
-        piece: Piece,
+        is_pv: bool,
     ) {
-        self.table[(key & KEY) as usize] = PositionEntry {
-            key,
+        let cluster = &self.table[(key & self.key) as usize];
+        let mut replace = 0;
+        let mut replace_value = i32::MAX;
+        let mut best_move = best_move;
+        for i in 0..CLUSTER_SIZE {
+            let old_data = cluster.data[i].load(Ordering::Relaxed);
+            let old = PositionEntry::unpack(old_data);
+            if old.entry_type == EntryType::None {
+                replace = i;
+                break;
+            }
+            if cluster.keys[i].load(Ordering::Relaxed) == key_check(key, old_data) {
+                if !is_pv
+                    && depth < old.depth
+                    && generation_of(old_data) == self.generation
+                {
+                    return;
+                }
+                //a result without a move keeps the move found before
+                if best_move == ChessMove::default() {
+                    best_move = old.best_move;
+                }
+                replace = i;
+                break;
+            }
+            let age = self.generation.wrapping_sub(generation_of(old_data)) & GENERATION_MASK;
+            let value = old.depth as i32 - AGE_WEIGHT * age as i32;
+            if value < replace_value {
+                replace = i;
+                replace_value = value;
+            }
+        }
+        let data = PositionEntry {
             eval,
+            static_eval,
             entry_type,
             depth,
             best_move,
-            piece,
-        };
-        return;
-    }
-    pub fn look_up_pawn_structure(&self, key: u64) -> Option<PawnEntry> {
-        let res = self.pawn_table[(key & PAWN_KEY) as usize];
-        if res.hash == key {
-            return Some(res);
         }
-        return None;
+        .pack(self.generation);
+        cluster.keys[replace].store(key_check(key, data), Ordering::Relaxed);
+        cluster.data[replace].store(data, Ordering::Relaxed);
     }
-    pub fn set_pawn_struct(
-        &mut self,
-        hash: u64,
-        w_filesets: u8,
-        b_filesets: u8,
-        w_pst: (i16, i16),
-        b_pst: (i16, i16),
-        eval: i16,
-    ) {
-        self.pawn_table[(hash & PAWN_KEY) as usize] = PawnEntry {
-            hash,
-            w_filesets,
-            b_filesets,
-            w_pst,
-            b_pst,
-            eval,
-        };
+    pub fn clear(&self) {
+        for cluster in self.table.iter() {
+            for i in 0..CLUSTER_SIZE {
+                cluster.keys[i].store(0, Ordering::Relaxed);
+                cluster.data[i].store(0, Ordering::Relaxed);
+            }
+        }
     }
-    pub fn clear(&mut self) {
-        //pawn table isn't cleared because of low collision probability
-        self.table = vec![
-            PositionEntry {
-                key: 0,
-                eval: 0,
-                entry_type: EntryType::None,
-                depth: 0,
-                best_move: ChessMove::default(),
-                piece: Piece::King,
-            };
-            NUM_OF_POSITIONS
-        ];
-        self.killers = [[ChessMove::default(); KILLERS_PER_PLY]; KILLER_PLIES];
-        self.history = [[[0; 64]; 6]; 2];
-        self.bf_history = [[[1; 64]; 6]; 2];
-        self.counters = [[[ChessMove::default(); 64]; 6]; 2];
+    //permille of entries written by the current search, sampled from the start of the table
+    pub fn hashfull(&self) -> usize {
+        let sample = &self.table[..self.table.len().min(1000 / CLUSTER_SIZE)];
+        let used = sample
+            .iter()
+            .flat_map(|cluster| cluster.data.iter())
+            .filter(|d| {
+                let data = d.load(Ordering::Relaxed);
+                return PositionEntry::unpack(data).entry_type != EntryType::None
+                    && generation_of(data) == self.generation;
+            })
+            .count();
+        return used * 1000 / (sample.len() * CLUSTER_SIZE);
     }
     pub fn get_pv(&self, board: &Position) -> Vec<ChessMove> {
         let mut pv = Vec::<ChessMove>::new();
         let mut hash = board.get_hash();
         let mut b = *board;
-        loop {
+        while pv.len() < MAX_PV_LENGTH {
             let res = self.look_up_pos(hash, &b);
-            if res.is_none() {
+            if res.is_none() || !b.board.legal(res.unwrap().best_move) {
                 break;
             }
             b = b.make_move_new(res.unwrap().best_move);
@@ -159,33 +256,131 @@ impl TranspositionTable {
         }
         return pv;
     }
-    pub fn get_killers(&self, ply: usize) -> &Killers {
-        if ply >= KILLER_PLIES {
-            return &self.default_killers;
-        }
-        return &self.killers[ply];
-    }
-    pub fn store_killer(
-        &mut self,
-        ply: usize,
-        depth: i32,
-        mv: ChessMove,
-        piece: Piece,
-        color: usize,
-        prev_piece: Piece,
-        prev_sq: Square,
-    ) {
-        //set move for history heuristic
-        self.history[color][piece.to_index()][mv.get_dest().to_index()] +=
-            (1 << depth) / (self.bf_history[color][piece.to_index()][mv.get_dest().to_index()]);
-        //set move for counter move heuristic
-        self.counters[color][prev_piece.to_index()][prev_sq.to_index()] = mv;
-        if ply >= KILLER_PLIES || self.killers[ply].contains(&mv) {
-            return;
+}
+impl PawnTable {
+    pub fn init(mb: usize) -> PawnTable {
+        let mut x = PawnTable {
+            table: Vec::new(),
+            key: 0,
+        };
+        x.set_size(mb);
+        return x;
+    }
+    pub fn set_size(&mut self, mb: usize) {
+        let entries = entries_for_size::<PawnEntry>(mb);
+        self.table = vec![PawnEntry::default(); entries];
+        self.key = entries as u64 - 1;
+    }
+    pub fn capacity(&self) -> usize {
+        return self.table.len();
+    }
+    pub fn look_up_pawn_structure(&self, key: u64) -> Option<PawnEntry> {
+        let res = self.table[(key & self.key) as usize];
+        if res.hash == key {
+            return Some(res);
         }
-        for i in 1..KILLERS_PER_PLY {
-            self.killers[ply][i] = self.killers[ply][i - 1];
+        return None;
+    }
+    pub fn set_pawn_struct(&mut self, hash: u64, w_filesets: u8, b_filesets: u8, eval: Score) {
+        self.table[(hash & self.key) as usize] = PawnEntry {
+            hash,
+            w_filesets,
+            b_filesets,
+            eval,
+        };
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess::{Piece, Square};
+    use std::str::FromStr;
+    fn entry(
+        eval: i16,
+        static_eval: i16,
+        entry_type: EntryType,
+        depth: u8,
+        mv: &str,
+    ) -> PositionEntry {
+        return PositionEntry {
+            eval,
+            static_eval,
+            entry_type,
+            depth,
+            best_move: ChessMove::from_str(mv).unwrap(),
+        };
+    }
+    fn same(a: &PositionEntry, b: &PositionEntry) -> bool {
+        return a.eval == b.eval
+            && a.static_eval == b.static_eval
+            && a.entry_type == b.entry_type
+            && a.depth == b.depth
+            && a.best_move == b.best_move;
+    }
+    #[test]
+    fn entries_unpack_unchanged() {
+        let entries = [
+            entry(35, -12, EntryType::Exact, 7, "e2e4"),
+            entry(-9950, -i16::MAX, EntryType::UpperBound, 0, "a1a1"),
+            entry(i16::MAX, 300, EntryType::LowerBound, 255, "h7h8q"),
+            entry(-1, 1, EntryType::LowerBound, 12, "b2a1n"),
+        ];
+        for e in entries.iter() {
+            for generation in [0, 1, GENERATION_MASK] {
+                let data = e.pack(generation);
+                assert!(same(&PositionEntry::unpack(data), e));
+                assert_eq!(generation_of(data), generation);
+            }
         }
-        self.killers[ply][0] = mv;
+        let promotion = PositionEntry::unpack(entries[2].pack(5)).best_move;
+        assert_eq!(promotion.get_promotion(), Some(Piece::Queen));
+        assert_eq!(promotion.get_dest(), Square::H8);
+    }
+    #[test]
+    fn key_check_rejects_torn_entries() {
+        let key = 0x9d39_247e_3377_6d41;
+        let a = entry(50, 40, EntryType::Exact, 9, "g1f3").pack(3);
+        let b = entry(-20, 40, EntryType::LowerBound, 4, "d2d4").pack(3);
+        //the key check of one write stored next to the data of another
+        assert_ne!(key_check(key, a), key_check(key, b));
+        assert_ne!(key_check(key, a), key_check(key ^ (1 << 60), a));
+    }
+    #[test]
+    fn stored_positions_are_found() {
+        let mut tt = TranspositionTable::init(1);
+        tt.new_search();
+        let pos = Position::default();
+        let key = pos.get_hash();
+        let e4 = ChessMove::from_str("e2e4").unwrap();
+        assert!(tt.look_up_pos(key, &pos).is_none());
+        tt.set_pos(key, 25, 10, EntryType::Exact, 6, e4, true);
+        let found = tt.look_up_pos(key, &pos).unwrap();
+        assert!(same(&found, &entry(25, 10, EntryType::Exact, 6, "e2e4")));
+        //a shallower bound from the same search keeps the deeper entry
+        tt.set_pos(key, -5, 10, EntryType::UpperBound, 2, ChessMove::default(), false);
+        assert_eq!(tt.look_up_pos(key, &pos).unwrap().depth, 6);
+        //so does an exact quiescence result
+        tt.set_pos(key, 5, 10, EntryType::Exact, 0, ChessMove::default(), false);
+        assert_eq!(tt.look_up_pos(key, &pos).unwrap().depth, 6);
+        //a deeper result without a move keeps the old move
+        tt.set_pos(key, 30, 10, EntryType::LowerBound, 8, ChessMove::default(), false);
+        let found = tt.look_up_pos(key, &pos).unwrap();
+        assert!(same(
+            &found,
+            &entry(30, 10, EntryType::LowerBound, 8, "e2e4")
+        ));
+        //a shallower result from a pv node replaces it
+        tt.set_pos(key, 15, 10, EntryType::Exact, 3, e4, true);
+        assert_eq!(tt.look_up_pos(key, &pos).unwrap().depth, 3);
+        //so does any result once the entry is from an older search
+        tt.new_search();
+        tt.set_pos(key, 0, 10, EntryType::Exact, 0, ChessMove::default(), false);
+        assert!(same(
+            &tt.look_up_pos(key, &pos).unwrap(),
+            &entry(0, 10, EntryType::Exact, 0, "e2e4")
+        ));
+        //a move that isn't legal in the position is never returned
+        let other = Position::new("k7/8/8/8/8/8/8/K7 w - - 0 1");
+        assert!(tt.look_up_pos(key, &other).is_none());
     }
 }
@@ -1,90 +1,159 @@
 use crate::board::Position;
+use crate::search::SEARCH_STACK_SIZE;
+use crate::time_manager::TimeManager;
 use crate::{
     board_util::print_board,
-    book::init_book_full,
+    book::{init_book_from_file, init_book_full},
+    evaluate::print_evaluation,
+    options::{parse_setoption, OptionValue, DEFAULT_BOOK_FILE, OPTIONS},
     perft::go_perft,
-    search::start_search,
-    transposition_table::{
-        PawnEntry, PositionEntry, TranspositionTable, NUM_OF_PAWNS, NUM_OF_POSITIONS,
-    },
+    search::{start_search, SearchControl, SearchLimits, SearchOptions, ThreadData, MAX_DEPTH},
+    transposition_table::{PawnEntry, TranspositionTable, DEFAULT_HASH_MB, DEFAULT_PAWN_HASH_MB},
 };
 use chess::{ChessMove, Color};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
 use std::time::Instant;
 use std::{str::FromStr, time::Duration};
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 const AUTHOR: &str = "Reza";
 const ENGINENAME: &str = "Ruchess";
-fn allocate_time(my_time: u32) -> Duration {
-    let time = (my_time / 30).min(15000);
-    let dur = Duration::new(time as u64 / 1000, (time % 1000) * 1000000);
-    return dur;
+//value following a keyword of the go command
+fn go_value<T: FromStr>(args: &[&str], key: &str) -> Option<T> {
+    let i = args.iter().position(|r| *r == key)?;
+    return args.get(i + 1)?.parse::<T>().ok();
 }
-fn add_repetition(table: &mut Vec<(u64, u8)>, hash: u64) {
-    for i in 0..table.len() {
-        if table[i].0 == hash {
-            table[i] = (hash, table[i].1 + 1);
-            return;
+fn parse_limits(args: &[&str], board: &Position) -> SearchLimits {
+    let mut search_moves = Vec::new();
+    if let Some(i) = args.iter().position(|r| *r == "searchmoves") {
+        for m in &args[(i + 1)..] {
+            match ChessMove::from_str(m) {
+                Ok(mv) if board.board.legal(mv) => search_moves.push(mv),
+                _ => break,
+            }
         }
     }
-    table.push((hash, 1));
+    return SearchLimits {
+        depth: go_value::<u8>(args, "depth").map_or(MAX_DEPTH, |d| d.clamp(1, MAX_DEPTH)),
+        nodes: go_value(args, "nodes"),
+        mate: go_value(args, "mate"),
+        time: None,
+        search_moves,
+    };
+}
+//signals the running search (if any) to stop and waits for it to print its bestmove
+fn stop_search(search_thread: &mut Option<JoinHandle<()>>, control: &SearchControl) {
+    if let Some(handle) = search_thread.take() {
+        control.stop.store(true, Ordering::Relaxed);
+        handle.join().unwrap();
+    }
 }
-fn get_possible_drawns(table: &Vec<(u64, u8)>) -> Vec<u64> {
-    return table
-        .into_iter()
-        .filter(|a| a.1 == 2)
-        .map(|i| i.0)
-        .collect();
+fn clear_hash(tt: &TranspositionTable, threads: &Mutex<Vec<ThreadData>>) {
+    tt.clear();
+    for td in threads.lock().unwrap().iter_mut() {
+        td.clear();
+    }
 }
 pub fn uci() {
-    let mut log = false;
+    let mut debug = false;
     let mut prev_cmd = String::new();
     let scanner = std::io::stdin();
     let mut line = String::new();
     let mut board = Position::default();
-    let mut tt = TranspositionTable::init();
+    //the table is shared with the search threads without a lock, only resizing and starting a
+    //new search need it to themselves and they wait for the search to finish first
+    let mut tt = Arc::new(TranspositionTable::init(DEFAULT_HASH_MB));
+    let threads = Arc::new(Mutex::new(vec![ThreadData::new(DEFAULT_PAWN_HASH_MB)]));
+    let mut pawn_hash_mb = DEFAULT_PAWN_HASH_MB;
+    //the thread data is locked while searching, so the uci command doesn't ask it for this
+    let mut pawn_capacity = threads.lock().unwrap()[0].pawn_table.capacity();
+    let mut search_thread: Option<JoinHandle<()>> = None;
+    let control = Arc::new(SearchControl::default());
     let mut book = init_book_full();
-    let mut repetition_table: Vec<(u64, u8)> = Vec::new();
+    //hashes of the game's positions before the current one, for repetition detection
+    let mut game_hashes: Vec<u64> = Vec::new();
     let mut use_book = true;
+    let mut own_book = true;
+    let mut move_overhead: u64 = 10;
+    let mut contempt: i16 = 0;
+    let mut multi_pv: usize = 1;
+    let mut quiesce_checks = false;
     let mut book_move = String::from("");
+    let mut go_start = Instant::now();
+    let mut ponder_time: Option<TimeManager> = None;
     loop {
         line.clear();
-        scanner.read_line(&mut line).unwrap();
+        if scanner.read_line(&mut line).unwrap() == 0 {
+            //stdin closed, let the current search finish before exiting
+            if control.is_infinite() {
+                control.stop.store(true, Ordering::Relaxed);
+            }
+            if let Some(handle) = search_thread.take() {
+                handle.join().unwrap();
+            }
+            return;
+        }
         let string = line.trim();
         let args: Vec<&str> = string.split(" ").collect();
         match string {
             "uci" => {
                 println!("id name {} v{}", ENGINENAME, VERSION);
                 println!("id author {}", AUTHOR);
+                for option in OPTIONS.iter() {
+                    println!("{}", option.declaration());
+                }
                 println!(
                     "info {} mb hash_table {} entries",
-                    (std::mem::size_of::<PositionEntry>() * NUM_OF_POSITIONS) as f32 / 1048576.0,
-                    NUM_OF_POSITIONS
+                    tt.size_in_bytes() as f32 / 1048576.0,
+                    tt.capacity()
                 );
                 println!(
                     "info {} mb pawn_hash_table {} entries",
-                    (std::mem::size_of::<PawnEntry>() * NUM_OF_PAWNS) as f32 / 1048576.0,
-                    NUM_OF_PAWNS
+                    (std::mem::size_of::<PawnEntry>() * pawn_capacity) as f32 / 1048576.0,
+                    pawn_capacity
                 );
                 println!("uciok");
             }
             "isready" => println!("readyok"),
+            "stop" => stop_search(&mut search_thread, &control),
+            "ponderhit" => {
+                //the opponent played the expected move, keep searching on our own clock
+                if let Some(tm) = ponder_time {
+                    control.set_time_limits(
+                        go_start.elapsed() + tm.soft,
+                        go_start.elapsed() + tm.hard,
+                    );
+                }
+                control.infinite.store(false, Ordering::Relaxed);
+            }
             "ucinewgame" => {
+                stop_search(&mut search_thread, &control);
                 board = Position::default();
-                tt.clear();
+                clear_hash(&tt, &threads);
                 book.reset();
-                repetition_table.clear();
+                game_hashes.clear();
+            }
+            "quit" => {
+                stop_search(&mut search_thread, &control);
+                std::process::exit(0)
             }
-            "quit" => std::process::exit(0),
-            "log" => log = true,
+            "debug on" | "log" => debug = true,
+            "debug off" => debug = false,
             "print" => print_board(&board.board),
-            "pv" => println!(
-                "{:?}",
-                tt.get_pv(&board)
-                    .into_iter()
-                    .map(|a| a.to_string())
-                    .collect::<Vec<String>>()
-            ),
+            "eval" => print_evaluation(&board),
+            "pv" => {
+                stop_search(&mut search_thread, &control);
+                println!(
+                    "{:?}",
+                    tt.get_pv(&board)
+                        .into_iter()
+                        .map(|a| a.to_string())
+                        .collect::<Vec<String>>()
+                )
+            }
             a if a.starts_with("position") => {
+                stop_search(&mut search_thread, &control);
                 if prev_cmd.contains("moves") && string.starts_with(&prev_cmd.trim()) {
                     let move_list = string[(prev_cmd.trim().len())..string.len()].trim();
                     let i: usize = string.find("moves").unwrap();
@@ -97,13 +166,13 @@ pub fn uci() {
                         None => use_book = false,
                     }
                     for m in move_list.split(" ") {
+                        game_hashes.push(board.get_hash());
                         board = board.make_move_new(ChessMove::from_str(m).unwrap());
-                        add_repetition(&mut repetition_table, board.get_hash());
                     }
                 } else {
-                    tt.clear();
+                    clear_hash(&tt, &threads);
                     book.reset();
-                    repetition_table.clear();
+                    game_hashes.clear();
                     if string.starts_with("position fen") {
                         use_book = false;
                         let fen = string[13..string.len()].to_owned();
@@ -116,8 +185,8 @@ pub fn uci() {
                         let i: usize = string.find("moves").unwrap();
                         let move_list = string[(i + 5)..string.len()].trim();
                         for m in move_list.split(" ") {
+                            game_hashes.push(board.get_hash());
                             board = board.make_move_new(ChessMove::from_str(m).unwrap());
-                            add_repetition(&mut repetition_table, board.get_hash());
                         }
                         let book_res = book.check(move_list);
                         match book_res {
@@ -141,6 +210,7 @@ pub fn uci() {
                 prev_cmd = String::from(string);
             }
             a if a.starts_with("go") => {
+                stop_search(&mut search_thread, &control);
                 if a.contains("perft") {
                     let i = args.iter().position(|r| *r == "perft").unwrap() + 1;
                     let depth = args[i].parse::<usize>();
@@ -153,48 +223,129 @@ pub fn uci() {
                         println!("invalid depth")
                     }
                 } else {
-                    if use_book && book_move != "".to_string() {
+                    let infinite = args.contains(&"infinite");
+                    let ponder = args.contains(&"ponder");
+                    if own_book && use_book && book_move != "".to_string() && !infinite && !ponder {
                         println!("bestmove {}", book_move);
                         book_move = String::from("");
                         continue;
                     }
-                    let allocated_time: Duration;
-                    if a.contains("movetime") {
-                        let i = args.iter().position(|r| *r == "movetime").unwrap() + 1;
-                        let time = args[i].parse::<u32>().unwrap();
-                        allocated_time = Duration::new(time as u64 / 1000, (time % 1000) * 1000000);
-                    } else if a.contains("wtime") && a.contains("btime") {
-                        let wtime = args[args.iter().position(|r| *r == "wtime").unwrap() + 1]
-                            .parse::<u32>()
-                            .unwrap();
-                        let btime = args[args.iter().position(|r| *r == "btime").unwrap() + 1]
-                            .parse::<u32>()
-                            .unwrap();
-                        if board.side_to_move() == Color::White {
-                            allocated_time = allocate_time(wtime);
-                        } else {
-                            allocated_time = allocate_time(btime);
-                        }
+                    let mut limits = parse_limits(&args, &board);
+                    let (time, inc) = if board.side_to_move() == Color::White {
+                        ("wtime", "winc")
                     } else {
-                        allocated_time = Duration::new(3, 0);
+                        ("btime", "binc")
+                    };
+                    let overhead = Duration::from_millis(move_overhead);
+                    if let Some(movetime) = go_value::<u64>(&args, "movetime") {
+                        limits.time = Some(TimeManager::fixed(
+                            Duration::from_millis(movetime),
+                            overhead,
+                        ));
+                    } else if let Some(time_left) = go_value::<u64>(&args, time) {
+                        limits.time = Some(TimeManager::new(
+                            Duration::from_millis(time_left),
+                            Duration::from_millis(go_value::<u64>(&args, inc).unwrap_or(0)),
+                            go_value(&args, "movestogo"),
+                            overhead,
+                        ));
+                    } else if limits.depth == MAX_DEPTH
+                        && limits.nodes.is_none()
+                        && limits.mate.is_none()
+                        && !infinite
+                    {
+                        //depth, node and mate searches aren't timed
+                        limits.time = Some(TimeManager::fixed(Duration::new(3, 0), overhead));
                     }
-                    let res = start_search(
-                        &board,
-                        50,
-                        allocated_time,
-                        &mut tt,
-                        &get_possible_drawns(&repetition_table),
-                        log,
+                    go_start = Instant::now();
+                    ponder_time = limits.time;
+                    Arc::get_mut(&mut tt).unwrap().new_search();
+                    let tt = Arc::clone(&tt);
+                    let threads = Arc::clone(&threads);
+                    let game_hashes = game_hashes.clone();
+                    let board = board;
+                    let control = Arc::clone(&control);
+                    control.stop.store(false, Ordering::Relaxed);
+                    control
+                        .infinite
+                        .store(infinite || ponder, Ordering::Relaxed);
+                    let options = SearchOptions {
+                        contempt,
+                        multi_pv,
+                        quiesce_checks,
+                        debug,
+                    };
+                    search_thread = Some(
+                        std::thread::Builder::new()
+                            .stack_size(SEARCH_STACK_SIZE)
+                            .spawn(move || {
+                                let res = start_search(
+                                    &board,
+                                    &limits,
+                                    &game_hashes,
+                                    &tt,
+                                    &mut threads.lock().unwrap(),
+                                    &control,
+                                    options,
+                                );
+                                //uci's null move, there was no legal move to search
+                                if res.best_move == ChessMove::default() {
+                                    println!("bestmove 0000");
+                                    return;
+                                }
+                                let ponder_move = tt
+                                    .get_pv(&board.make_move_new(res.best_move))
+                                    .first()
+                                    .copied();
+                                match ponder_move {
+                                    Some(mv) => {
+                                        println!("bestmove {} ponder {}", res.best_move, mv)
+                                    }
+                                    None => println!("bestmove {}", res.best_move),
+                                }
+                            })
+                            .unwrap(),
                     );
-                    println!("bestmove {}", res.best_move.to_string());
                 }
             }
             a if a.starts_with("setoption") => {
-                let name = args[args.iter().position(|r| *r == "name").unwrap() + 1];
-                let value = args[args.iter().position(|r| *r == "value").unwrap() + 1];
-                match name {
-                    "log" => log = value == "true",
-                    _ => {}
+                stop_search(&mut search_thread, &control);
+                match parse_setoption(string) {
+                    Ok((name, value)) => match (name, value) {
+                        ("Hash", OptionValue::Spin(mb)) => {
+                            Arc::get_mut(&mut tt).unwrap().set_hash_size(mb as usize)
+                        }
+                        ("PawnHash", OptionValue::Spin(mb)) => {
+                            pawn_hash_mb = mb as usize;
+                            for td in threads.lock().unwrap().iter_mut() {
+                                td.pawn_table.set_size(pawn_hash_mb);
+                            }
+                            pawn_capacity = threads.lock().unwrap()[0].pawn_table.capacity();
+                        }
+                        ("Threads", OptionValue::Spin(n)) => threads
+                            .lock()
+                            .unwrap()
+                            .resize_with(n as usize, || ThreadData::new(pawn_hash_mb)),
+                        ("Clear Hash", _) => clear_hash(&tt, &threads),
+                        ("Move Overhead", OptionValue::Spin(ms)) => move_overhead = ms as u64,
+                        ("Contempt", OptionValue::Spin(cp)) => contempt = cp as i16,
+                        ("MultiPV", OptionValue::Spin(n)) => multi_pv = n as usize,
+                        ("QuiescenceChecks", OptionValue::Check(b)) => quiesce_checks = b,
+                        ("OwnBook", OptionValue::Check(b)) => own_book = b,
+                        ("BookFile", OptionValue::String(path)) => {
+                            if path == DEFAULT_BOOK_FILE {
+                                book = init_book_full();
+                            } else {
+                                match init_book_from_file(&path) {
+                                    Ok(b) => book = b,
+                                    Err(e) => println!("info string can't read {}: {}", path, e),
+                                }
+                            }
+                        }
+                        ("Log", OptionValue::Check(b)) => debug = b,
+                        _ => {}
+                    },
+                    Err(e) => println!("info string {}", e),
                 }
             }
             _ => {}